@@ -1,5 +1,7 @@
 #pragma once
 
+#include <cstddef>
+
 extern "C"
 {
   struct MeridianVersion
@@ -9,8 +11,34 @@ extern "C"
     int patch;
   };
 
+  struct MeridianProfilePoint
+  {
+    double latitude;
+    double longitude;
+    float distance;
+    float elevation;
+  };
+
+  struct MeridianProfile
+  {
+    MeridianProfilePoint* points;
+    size_t count;
+  };
+
+  enum MeridianInterpolation
+  {
+    MeridianInterpolationNearest = 0,
+    MeridianInterpolationBilinear = 1,
+    MeridianInterpolationBicubic = 2
+  };
+
   MeridianVersion meridian_version();
   const char* meridian_binary_directory();
   int meridian_elevation(double latitude, double longitude);
+  float meridian_elevation_interpolated(double latitude, double longitude, int interpolation);
+  size_t meridian_elevations(const double* coordinates, size_t count, float* elevations);
+  MeridianProfile meridian_build_profile(const double* path, size_t count, float step,
+                                         bool inflections_only);
+  void meridian_free_profile(MeridianProfile profile);
   bool meridian_enable_logger();
 }
\ No newline at end of file
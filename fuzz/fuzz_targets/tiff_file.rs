@@ -0,0 +1,21 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use meridian::geotiff::{GeoTiff, TiffFile};
+
+// cargo +nightly fuzz run tiff_file fuzz/corpus/tiff_file fuzz/seeds/tiff_file
+// the seeds are hand-made malicious files, such as a tiny header declaring a huge tile
+fuzz_target!(|data: &[u8]| {
+  let _ = TiffFile::from_bytes(data);
+
+  // blocks are decoded lazily, so read a few pixels of every level as well
+  if let Ok(tiff) = GeoTiff::from_bytes(data) {
+    for level in 0..tiff.levels() {
+      if let Some((width, length)) = tiff.level_size(level) {
+        for (x, y) in [(0, 0), (width / 2, length / 2), (width - 1, length - 1)] {
+          let _ = tiff.get_pixel(level, x, y);
+        }
+      }
+    }
+  }
+});
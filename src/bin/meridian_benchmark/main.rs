@@ -1,16 +1,57 @@
 use std::env;
+use std::thread;
+use std::time::Instant;
 use meridian_positioning::{GeoCoordinate, GeoRectangle};
-use meridian::Chunk;
+use meridian::{Chunk, TileIdentity};
 use meridian::config::CONFIG;
 use meridian::heightmap::ElevationPrefetcher;
 // use meridian::heightmap::{convert_georectangle, ImageFormat, Resolution};
 use meridian::init_logger;
 
+/// Times loading the tile at `path` the way the tile storage does and finding
+/// its elevation range. Tiles used to be decompressed whole on one thread while
+/// loading; now a query decodes only its block, and scanning the whole tile
+/// decodes it on all cores.
+fn benchmark_loading(path: &str)
+{
+  let cores = thread::available_parallelism()
+    .map_or(1, |x| x.get());
+  let load = || TileIdentity::new(path.to_string()).expect("Failed to open tile");
+
+  // warm up the page cache so that every run reads the file from memory
+  load();
+
+  let start = Instant::now();
+  let tile = load();
+  tile.data.decode_level(0, 1).expect("Failed to decode tile");
+  tile.elevation_range().expect("Failed to scan tile");
+  println!("Before: loaded, decoded on 1 thread and scanned {} in {} ms", path,
+           start.elapsed().as_millis());
+
+  let start = Instant::now();
+  let tile = load();
+  let (width, length) = tile.size;
+  tile.data.get_pixel(0, width / 2, length / 2).expect("Failed to read tile");
+  println!("After: loaded {} and read one elevation in {} ms", path,
+           start.elapsed().as_millis());
+
+  let start = Instant::now();
+  tile.elevation_range().expect("Failed to scan tile");
+  println!("After: decoded on {} thread(s) and scanned {} in {} ms", cores, path,
+           start.elapsed().as_millis());
+}
+
 fn main()
 {
   init_logger();
   println!("Starting MeridianBenchmark...");
 
+  if let Some(path) = env::args().nth(1) {
+    benchmark_loading(&path);
+    println!("Done!");
+    return;
+  }
+
   let rectangle = GeoRectangle::from_center_meters(
     GeoCoordinate::new(45.285843, 34.238057, None),
     300_000.0,
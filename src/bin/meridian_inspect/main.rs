@@ -0,0 +1,321 @@
+use std::env;
+use std::process::ExitCode;
+use std::thread;
+use json::{object, JsonValue};
+use meridian::geotiff::{compression_name, tag_name, GeoTiff, Ifd, TiffFile, TiffParserError, Value,
+                        TAG_BITS_PER_SAMPLE, TAG_COMPRESSION, TAG_SAMPLE_FORMAT};
+
+const USAGE: &str = "Usage: meridian-inspect [--json] [--no-stats] <file>...";
+
+struct Options
+{
+  json: bool,
+  stats: bool,
+  paths: Vec<String>
+}
+
+/// Statistics of the valid samples of one band of the full resolution raster.
+struct Statistics
+{
+  min: f64,
+  max: f64,
+  mean: f64,
+  valid: usize,
+  voids: usize
+}
+
+/// Tags of an IFD and its SubIFDs, kept once the file is converted to a `GeoTiff`.
+struct Directory
+{
+  entries: Vec<(u16, Value)>,
+  sub_ifds: Vec<Directory>
+}
+
+impl Directory
+{
+  fn new(ifd: &Ifd) -> Self
+  {
+    Self {
+      entries: ifd.entries
+        .iter()
+        .map(|entry| (entry.tag(), entry.value().clone()))
+        .collect(),
+      sub_ifds: ifd.sub_ifds()
+        .iter()
+        .map(Directory::new)
+        .collect()
+    }
+  }
+}
+
+struct Report
+{
+  path: String,
+  /// Compression and sample types of the first IFD.
+  format: Option<(String, Vec<String>)>,
+  ifds: Vec<Directory>,
+  geotiff: GeoTiff,
+  statistics: Vec<Statistics>
+}
+
+fn main() -> ExitCode
+{
+  let options = match parse_args(env::args().skip(1)) {
+    Some(options) => options,
+    None => {
+      eprintln!("{}", USAGE);
+      return ExitCode::FAILURE;
+    }
+  };
+
+  let mut status = ExitCode::SUCCESS;
+  let mut reports = JsonValue::new_array();
+  for path in &options.paths {
+    match inspect(path, options.stats) {
+      Ok(report) if options.json => reports.push(report_json(&report)).unwrap(),
+      Ok(report) => print_report(&report),
+      Err(e) => {
+        eprintln!("{}: {}", path, e);
+        if options.json {
+          reports.push(object! { path: path.as_str(), error: e.to_string() }).unwrap();
+        }
+        status = ExitCode::FAILURE;
+      }
+    }
+  }
+  if options.json {
+    println!("{}", reports.pretty(2));
+  }
+  status
+}
+
+fn parse_args(args: impl Iterator<Item = String>) -> Option<Options>
+{
+  let mut options = Options { json: false, stats: true, paths: vec![] };
+  for arg in args {
+    match arg.as_str() {
+      "--json" => options.json = true,
+      "--no-stats" => options.stats = false,
+      _ if arg.starts_with('-') => return None,
+      _ => options.paths.push(arg)
+    }
+  }
+  match options.paths.is_empty() {
+    true => None,
+    false => Some(options)
+  }
+}
+
+fn inspect(path: &str, stats: bool) -> Result<Report, TiffParserError>
+{
+  let tiff = TiffFile::from_file(path)?;
+  let format = tiff.ifds()
+    .first()
+    .map(|ifd| (compression(ifd), sample_types(ifd)));
+  let ifds = tiff.ifds()
+    .iter()
+    .map(Directory::new)
+    .collect();
+  let geotiff = GeoTiff::from_tiff(tiff)?;
+  let statistics = match stats {
+    true => (0..geotiff.bands())
+      .map(|band| statistics(&geotiff, band))
+      .collect::<Result<Vec<_>, _>>()?,
+    false => vec![]
+  };
+  Ok(Report { path: path.to_string(), format, ifds, geotiff, statistics })
+}
+
+fn statistics(tiff: &GeoTiff, band: usize) -> Result<Statistics, TiffParserError>
+{
+  let threads = thread::available_parallelism()
+    .map_or(1, |x| x.get());
+  tiff.decode_level(0, threads)?;
+  let (width, length) = tiff.size().unwrap_or((0, 0));
+  let mut stats = Statistics { min: f64::MAX, max: f64::MIN, mean: 0.0, valid: 0, voids: 0 };
+  let mut sum = 0.0;
+  for y in 0..length {
+    for x in 0..width {
+      match tiff.get_band_pixel(0, band, x, y)? {
+        Some(value) if !value.is_nan() => {
+          stats.min = stats.min.min(value);
+          stats.max = stats.max.max(value);
+          sum += value;
+          stats.valid += 1;
+        }
+        _ => stats.voids += 1
+      }
+    }
+  }
+  if stats.valid > 0 {
+    stats.mean = sum / stats.valid as f64;
+  }
+  Ok(stats)
+}
+
+fn compression(ifd: &Ifd) -> String
+{
+  match ifd.get_value(TAG_COMPRESSION).and_then(|x| x.short()) {
+    Ok(x) => compression_name(x)
+      .map_or(format!("unknown ({})", x), |name| name.to_string()),
+    Err(_) => "unknown".to_string()
+  }
+}
+
+/// Sample type of every band, e.g. `Float32` or `Int16`.
+fn sample_types(ifd: &Ifd) -> Vec<String>
+{
+  let bits = ifd.get_value(TAG_BITS_PER_SAMPLE)
+    .and_then(|x| x.uints())
+    .unwrap_or_default();
+  let formats = ifd.get_value(TAG_SAMPLE_FORMAT)
+    .and_then(|x| x.uints())
+    .unwrap_or_default();
+  bits.iter()
+    .enumerate()
+    .map(|(i, bits)| match formats.get(i).or(formats.first()).copied().unwrap_or(1) {
+      1 => format!("UInt{}", bits),
+      2 => format!("Int{}", bits),
+      3 => format!("Float{}", bits),
+      format => format!("{}-bit samples of format {}", bits, format)
+    })
+    .collect()
+}
+
+fn print_report(report: &Report)
+{
+  let geotiff = &report.geotiff;
+  println!("{}", report.path);
+  let format = &report.format;
+  if let (Some((width, length)), Some((compression, sample_types))) = (geotiff.size(), format) {
+    println!("  Size: {}x{}, {} band(s) of {}", width, length, geotiff.bands(),
+             sample_types.join(", "));
+    println!("  Compression: {}", compression);
+  }
+  let levels: Vec<String> = (1..geotiff.levels())
+    .filter_map(|level| geotiff.level_size(level))
+    .map(|(width, length)| format!("{}x{}", width, length))
+    .collect();
+  if !levels.is_empty() {
+    println!("  Overviews: {}", levels.join(", "));
+  }
+  if let Some(nodata) = geotiff.nodata() {
+    println!("  NoData: {}", nodata);
+  }
+  if let Some(georeference) = geotiff.georeference() {
+    println!("  Geotransform: {:?}", georeference.transform.0);
+    println!("  Model type: {:?}, raster type: {:?}", georeference.model_type,
+             georeference.raster_type);
+    if let Some(epsg) = georeference.epsg {
+      println!("  EPSG: {}", epsg);
+    }
+    if let Some(citation) = &georeference.citation {
+      println!("  Citation: {}", citation);
+    }
+    if let Some((width, length)) = geotiff.size() {
+      let (west, south, east, north) = georeference.bounds(width, length);
+      println!("  Bounds: west {}, south {}, east {}, north {}", west, south, east, north);
+    }
+  }
+  for (band, stats) in report.statistics.iter().enumerate() {
+    match stats.valid {
+      0 => println!("  Band {}: no valid samples, {} voids", band, stats.voids),
+      _ => println!("  Band {}: min {}, max {}, mean {:.3}, {} valid, {} voids",
+                    band, stats.min, stats.max, stats.mean, stats.valid, stats.voids)
+    }
+  }
+  for (index, ifd) in report.ifds.iter().enumerate() {
+    print_ifd(ifd, &format!("IFD {}", index), 1);
+  }
+}
+
+fn print_ifd(ifd: &Directory, title: &str, depth: usize)
+{
+  let indent = "  ".repeat(depth);
+  println!("{}{}:", indent, title);
+  for (tag, value) in &ifd.entries {
+    println!("{}  {} ({}): {}", indent, tag_name(*tag).unwrap_or("Unknown"), tag, value);
+  }
+  for (index, sub_ifd) in ifd.sub_ifds.iter().enumerate() {
+    print_ifd(sub_ifd, &format!("SubIFD {}", index), depth + 1);
+  }
+}
+
+fn report_json(report: &Report) -> JsonValue
+{
+  let geotiff = &report.geotiff;
+  let mut json = object! {
+    path: report.path.as_str(),
+    bands: geotiff.bands(),
+    levels: (0..geotiff.levels())
+      .filter_map(|level| geotiff.level_size(level))
+      .map(|(width, length)| object! { width: width, length: length })
+      .collect::<Vec<_>>(),
+    nodata: geotiff.nodata(),
+    ifds: report.ifds.iter().map(ifd_json).collect::<Vec<_>>()
+  };
+  if let Some((compression, sample_types)) = &report.format {
+    json["compression"] = compression.as_str().into();
+    json["sample_types"] = sample_types.clone().into();
+  }
+  if let Some(georeference) = geotiff.georeference() {
+    json["geotransform"] = georeference.transform.0.to_vec().into();
+    json["model_type"] = georeference.model_type.map(|x| format!("{:?}", x)).into();
+    json["raster_type"] = format!("{:?}", georeference.raster_type).into();
+    json["epsg"] = georeference.epsg.into();
+    json["citation"] = georeference.citation.clone().into();
+    if let Some((width, length)) = geotiff.size() {
+      let (west, south, east, north) = georeference.bounds(width, length);
+      json["bounds"] = object! { west: west, south: south, east: east, north: north };
+    }
+  }
+  if !report.statistics.is_empty() {
+    json["statistics"] = report.statistics
+      .iter()
+      .map(|stats| object! {
+        min: (stats.valid > 0).then_some(stats.min),
+        max: (stats.valid > 0).then_some(stats.max),
+        mean: (stats.valid > 0).then_some(stats.mean),
+        valid: stats.valid,
+        voids: stats.voids
+      })
+      .collect::<Vec<_>>()
+      .into();
+  }
+  json
+}
+
+fn ifd_json(ifd: &Directory) -> JsonValue
+{
+  let mut tags = JsonValue::new_array();
+  for (tag, value) in &ifd.entries {
+    tags.push(object! {
+      tag: *tag,
+      name: tag_name(*tag),
+      value: value_json(value)
+    }).unwrap();
+  }
+  object! {
+    tags: tags,
+    sub_ifds: ifd.sub_ifds.iter().map(ifd_json).collect::<Vec<_>>()
+  }
+}
+
+fn value_json(value: &Value) -> JsonValue
+{
+  match value {
+    Value::Bytes(x) | Value::Undefined(x) => x.clone().into(),
+    Value::Sbytes(x) => x.clone().into(),
+    Value::Shorts(x) => x.clone().into(),
+    Value::Sshorts(x) => x.clone().into(),
+    Value::Longs(x) => x.clone().into(),
+    Value::Slongs(x) => x.clone().into(),
+    Value::Long8s(x) | Value::Ifd8s(x) => x.clone().into(),
+    Value::Slong8s(x) => x.clone().into(),
+    Value::Rationals(x) => x.iter().map(|(n, d)| vec![*n, *d]).collect::<Vec<_>>().into(),
+    Value::Srationals(x) => x.iter().map(|(n, d)| vec![*n, *d]).collect::<Vec<_>>().into(),
+    Value::Floats(x) => x.clone().into(),
+    Value::Doubles(x) => x.clone().into(),
+    Value::Ascii(x) => x.as_str().into()
+  }
+}
@@ -1,11 +1,43 @@
 use meridian_positioning::GeoCoordinate;
+use num_derive::FromPrimitive;
 use crate::errors::Error;
-use crate::tile_storage::TileSignature;
+use crate::geotiff::GeoTransform;
+use crate::tile_storage::{TileIdentity, TileSignature, TileStorage};
 use crate::tile_storage::STORAGE;
-use crate::utils::validate_coordinate;
+use crate::utils::{decode_threads, validate_coordinate};
+
+/// How elevations between pixel centres are estimated.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, FromPrimitive)]
+pub enum Interpolation
+{
+  /// Value of the pixel holding the coordinate.
+  #[default]
+  Nearest,
+  /// Distance weighted mean of the 2x2 closest pixel centres.
+  Bilinear,
+  /// Catmull-Rom spline through the 4x4 closest pixel centres.
+  Bicubic
+}
+
+impl Interpolation
+{
+  /// Half of the side of the square of pixels the estimate is made from.
+  fn radius(&self) -> i64
+  {
+    match self
+    {
+      Interpolation::Nearest => 0,
+      Interpolation::Bilinear => 1,
+      Interpolation::Bicubic => 2
+    }
+  }
+}
 
 pub trait Elevation {
   fn elevation(&self) -> Result<f32, Error>;
+  fn elevation_at_resolution(&self, resolution: f32) -> Result<f32, Error>;
+  fn elevation_interpolated(&self, resolution: f32, interpolation: Interpolation)
+    -> Result<f32, Error>;
 }
 
 impl Elevation for GeoCoordinate
@@ -14,31 +46,401 @@ impl Elevation for GeoCoordinate
   {
     elevation_at((self.latitude, self.longitude))
   }
+
+  fn elevation_at_resolution(&self, resolution: f32) -> Result<f32, Error>
+  {
+    elevation_at_resolution((self.latitude, self.longitude), resolution)
+  }
+
+  fn elevation_interpolated(&self, resolution: f32, interpolation: Interpolation)
+    -> Result<f32, Error>
+  {
+    elevation_at_interpolated((self.latitude, self.longitude), resolution, interpolation)
+  }
 }
 
 pub fn elevation_at(coordinate: (f64, f64)) -> Result<f32, Error>
+{
+  elevation_at_resolution(coordinate, 0.0)
+}
+
+/// Reads the elevation from the coarsest overview of the tile whose pixels are
+/// no larger than `resolution` meters. Zero always reads full resolution data.
+pub fn elevation_at_resolution(coordinate: (f64, f64), resolution: f32) -> Result<f32, Error>
+{
+  elevation_at_interpolated(coordinate, resolution, Interpolation::Nearest)
+}
+
+pub fn elevations_at(coordinates: &[(f64, f64)]) -> Vec<Result<f32, Error>>
+{
+  elevations_at_resolution(coordinates, 0.0)
+}
+
+/// Batch version of [`elevation_at_resolution`]. Holds the storage lock once for
+/// the whole batch and looks every tile up only once for all of its points.
+/// Results are in the order of `coordinates`.
+pub fn elevations_at_resolution(coordinates: &[(f64, f64)], resolution: f32)
+  -> Vec<Result<f32, Error>>
+{
+  let mut storage = STORAGE
+    .lock()
+    .unwrap();
+  let mut results: Vec<Option<Result<f32, Error>>> = Vec::with_capacity(coordinates.len());
+  let mut queries = Vec::with_capacity(coordinates.len());
+  for (index, coordinate) in coordinates.iter().enumerate() {
+    match validate_coordinate(*coordinate) {
+      Ok(coord) => {
+        queries.push((TileSignature::from_f64(coord.0, coord.1), index, coord));
+        results.push(None);
+      }
+      Err(e) => results.push(Some(Err(e.into())))
+    }
+  }
+  queries.sort_unstable_by_key(|(key, index, _)| (*key, *index));
+
+  for group in queries.chunk_by(|a, b| a.0 == b.0) {
+    let key = group[0].0;
+    match tile(&mut storage, key) {
+      Ok(tile) => {
+        decode_nearest(tile, key, group, resolution);
+        for (_, index, coord) in group {
+          results[*index] = Some(nearest_elevation(tile, key, *coord, resolution));
+        }
+      }
+      // errors are not cloneable, the other points look the tile up again
+      Err(e) => {
+        results[group[0].1] = Some(Err(e));
+        for (_, index, coord) in &group[1..] {
+          results[*index] = Some(tile(&mut storage, key)
+            .and_then(|tile| nearest_elevation(tile, key, *coord, resolution)));
+        }
+      }
+    }
+  }
+
+  results
+    .into_iter()
+    .map(|x| x.expect("every query has a result"))
+    .collect()
+}
+
+/// Lowest and highest elevation of the tile holding the coordinate, `None` when
+/// the tile holds only voids. The whole tile is read on the first call for it.
+pub fn tile_elevation_range(coordinate: (f64, f64)) -> Result<Option<(f32, f32)>, Error>
+{
+  let mut storage = STORAGE
+    .lock()
+    .unwrap();
+  let coord = validate_coordinate(coordinate)?;
+  Ok(tile(&mut storage, TileSignature::from_f64(coord.0, coord.1))?
+    .elevation_range()?
+    .map(|(min, max)| (min as f32, max as f32)))
+}
+
+/// Same as [`elevation_at_resolution`], but estimates the elevation from the
+/// pixels around the coordinate. Pixels past the tile edge are read from the
+/// neighbouring tiles. Falls back to the nearest pixel when any of them is a void
+/// or is missing.
+pub fn elevation_at_interpolated(coordinate: (f64, f64), resolution: f32,
+                                 interpolation: Interpolation) -> Result<f32, Error>
 {
   let mut storage = STORAGE
     .lock()
     .unwrap();
   let coord = validate_coordinate(coordinate)?;
+  let (tile, position) = locate(&mut storage, coord, resolution)?;
+  let nearest = position.nearest(tile)?;
+  let radius = interpolation.radius();
+  if radius == 0 {
+    return nearest
+      .map(|x| x as f32)
+      .ok_or(Error::NoData(coord.0, coord.1));
+  }
+
+  // pixel centres lie at half-integer pixel coordinates
+  let center = (position.pixel.0 - 0.5, position.pixel.1 - 0.5);
+  let origin = (center.0.floor() as i64 - radius + 1, center.1.floor() as i64 - radius + 1);
+  let side = 2 * radius as usize;
+  let mut samples = vec![None; side * side];
+  let mut outside = Vec::new();
+  for j in 0..side {
+    for i in 0..side {
+      let (column, row) = (origin.0 + i as i64, origin.1 + j as i64);
+      match position.contains(column, row) {
+        true => samples[j * side + i] = position.sample(tile, column as usize, row as usize)?,
+        false => outside.push((j * side + i, position.geographic(column, row)))
+      }
+    }
+  }
+  for (index, coordinate) in outside {
+    samples[index] = validate_coordinate(coordinate)
+      .map_err(Error::from)
+      .and_then(|x| locate(&mut storage, x, resolution))
+      .and_then(|(tile, position)| position.nearest(tile))
+      .unwrap_or(None);
+  }
+
+  match samples.into_iter().collect::<Option<Vec<f64>>>() {
+    Some(samples) => Ok(interpolate(&samples, side, center.0 - center.0.floor(),
+                                    center.1 - center.1.floor()) as f32),
+    None => nearest
+      .map(|x| x as f32)
+      .ok_or(Error::NoData(coord.0, coord.1))
+  }
+}
+
+/// Position of a coordinate in the raster of a tile at the level picked for a
+/// query resolution.
+struct RasterPosition
+{
+  level: usize,
+  size: (usize, usize),
+  /// Maps the outer corners of the pixels of the level to longitude and latitude.
+  transform: GeoTransform,
+  /// Column and row of the coordinate, from the top-left corner of the raster.
+  pixel: (f64, f64)
+}
+
+impl RasterPosition
+{
+  fn contains(&self, column: i64, row: i64) -> bool
+  {
+    column >= 0 && row >= 0 && (column as usize) < self.size.0 && (row as usize) < self.size.1
+  }
+
+  /// Value of the pixel holding the coordinate. Coordinates on the eastern or
+  /// southern edge of the raster read the last column or row.
+  fn nearest(&self, tile: &TileIdentity) -> Result<Option<f64>, Error>
+  {
+    match self.nearest_pixel() {
+      Some((column, row)) => self.sample(tile, column, row),
+      None => Ok(None)
+    }
+  }
+
+  /// Column and row of the pixel [`RasterPosition::nearest`] reads.
+  fn nearest_pixel(&self) -> Option<(usize, usize)>
+  {
+    let index = |x: f64, size: usize| match x.floor() {
+      x if x < 0.0 => None,
+      x if x as usize == size => Some(size - 1),
+      x => Some(x as usize)
+    };
+    Some((index(self.pixel.0, self.size.0)?, index(self.pixel.1, self.size.1)?))
+  }
+
+  fn sample(&self, tile: &TileIdentity, column: usize, row: usize) -> Result<Option<f64>, Error>
+  {
+    match row < self.size.1 {
+      true => Ok(tile.data.get_pixel(self.level, column, self.size.1 - 1 - row)?),
+      false => Ok(None)
+    }
+  }
+
+  /// Latitude and longitude of the centre of a pixel, which may lie outside of
+  /// the tile.
+  fn geographic(&self, column: i64, row: i64) -> (f64, f64)
+  {
+    let (longitude, latitude) = self.transform
+      .pixel_to_model(column as f64 + 0.5, row as f64 + 0.5);
+    match longitude
+    {
+      x if x >= 180.0 => (latitude, x - 360.0),
+      x if x < -180.0 => (latitude, x + 360.0),
+      x => (latitude, x)
+    }
+  }
+}
+
+fn locate(storage: &mut TileStorage, coord: (f64, f64), resolution: f32)
+  -> Result<(&TileIdentity, RasterPosition), Error>
+{
   let key = TileSignature::from_f64(coord.0, coord.1);
-  let val = match storage.get(&key) {
-    Ok(x) => x,
-    Err(_) => storage.load(&key)?
-  };
-  let image_size = val.size;
-  let data = val.data.as_ref();
+  let tile = tile(storage, key)?;
+  let position = raster_position(tile, key, coord, resolution)?;
+  Ok((tile, position))
+}
+
+fn tile(storage: &mut TileStorage, key: TileSignature) -> Result<&TileIdentity, Error>
+{
+  match storage.has(key) {
+    true => storage.get(&key),
+    false => storage.load(&key)
+  }
+}
+
+fn nearest_elevation(tile: &TileIdentity, key: TileSignature, coord: (f64, f64), resolution: f32)
+  -> Result<f32, Error>
+{
+  raster_position(tile, key, coord, resolution)?
+    .nearest(tile)?
+    .map(|x| x as f32)
+    .ok_or(Error::NoData(coord.0, coord.1))
+}
+
+/// Decodes the blocks of `tile` holding the nearest pixels of `queries` on every
+/// core, rather than one after another as the points are read. Errors are left
+/// for the reads to report.
+fn decode_nearest(tile: &TileIdentity, key: TileSignature,
+                  queries: &[(TileSignature, usize, (f64, f64))], resolution: f32)
+{
+  let positions: Vec<_> = queries
+    .iter()
+    .filter_map(|(_, _, coord)| raster_position(tile, key, *coord, resolution).ok())
+    .collect();
+  let pixels: Vec<_> = positions
+    .iter()
+    .filter_map(|position| position.nearest_pixel().map(|pixel| (position, pixel)))
+    .filter(|(position, (_, row))| *row < position.size.1)
+    .map(|(position, (column, row))| (column, position.size.1 - 1 - row))
+    .collect();
+  // the level depends only on the tile and the resolution
+  if let Some(position) = positions.first() {
+    let _ = tile.data.decode_pixels(position.level, &pixels, decode_threads());
+  }
+}
+
+/// Maps a coordinate inside the tile `key` to the pixels of `tile` through the
+/// geotransform of the file. Files without one are assumed to cover the tile
+/// exactly.
+fn raster_position(tile: &TileIdentity, key: TileSignature, coord: (f64, f64), resolution: f32)
+  -> Result<RasterPosition, Error>
+{
+  let data = tile.data.as_ref();
   let tile_size = key.georectangle_size();
-  let requested_coordinate = GeoCoordinate::new(coord.0, coord.1, None);
-  let distance_2d = (
-    requested_coordinate.distance_to(&GeoCoordinate::new(coord.0, key.longitude as f64, None))?,
-    requested_coordinate.distance_to(&GeoCoordinate::new(key.latitude as f64, coord.1, None))?
-  );
-  let dn = (distance_2d.0 / (tile_size.1 as f32), distance_2d.1 / (tile_size.0 as f32));
-  let pixel_coords = ((dn.0 * image_size.0 as f32) as usize,
-                                      (dn.1 * image_size.1 as f32) as usize);
-  let value = data.get_pixel(pixel_coords.0, pixel_coords.1);
-
-  Ok(value as f32)
-}
\ No newline at end of file
+  let pixel_size = tile_size.1 as f32 / tile.size.0 as f32;
+  let level = data.level_for_decimation((resolution / pixel_size) as f64);
+  let size = data
+    .level_size(level)
+    .unwrap_or(tile.size);
+  let transform = data
+    .level_transform(level)
+    .unwrap_or(GeoTransform([key.longitude as f64, 1.0 / size.0 as f64, 0.0,
+                             key.latitude as f64 + 1.0, 0.0, -1.0 / size.1 as f64]));
+  let pixel = transform
+    .model_to_pixel(coord.1, coord.0)
+    .ok_or(Error::NoData(coord.0, coord.1))?;
+  Ok(RasterPosition { level, size, transform, pixel })
+}
+
+/// Interpolates a `side` x `side` row-major square of samples at `(tx, ty)`,
+/// measured from the centre of the sample at index `side / 2 - 1` in each
+/// direction.
+fn interpolate(samples: &[f64], side: usize, tx: f64, ty: f64) -> f64
+{
+  match side
+  {
+    2 => {
+      let upper = samples[0] + (samples[1] - samples[0]) * tx;
+      let lower = samples[2] + (samples[3] - samples[2]) * tx;
+      upper + (lower - upper) * ty
+    }
+    _ => {
+      let rows: Vec<f64> = samples
+        .chunks(4)
+        .map(|row| catmull_rom([row[0], row[1], row[2], row[3]], tx))
+        .collect();
+      catmull_rom([rows[0], rows[1], rows[2], rows[3]], ty)
+    }
+  }
+}
+
+/// Catmull-Rom spline between `p[1]` and `p[2]`.
+fn catmull_rom(p: [f64; 4], t: f64) -> f64
+{
+  p[1] + 0.5 * t * (p[2] - p[0]
+    + t * (2.0 * p[0] - 5.0 * p[1] + 4.0 * p[2] - p[3]
+    + t * (3.0 * (p[1] - p[2]) + p[3] - p[0])))
+}
+
+#[cfg(test)]
+mod tests
+{
+  use super::*;
+  use crate::geotiff::{GeoTiff, GeoTiffWriter, Samples};
+
+  #[test]
+  fn test_bilinear()
+  {
+    let samples = [0.0, 10.0, 20.0, 40.0];
+    assert_eq!(interpolate(&samples, 2, 0.0, 0.0), 0.0);
+    assert_eq!(interpolate(&samples, 2, 1.0, 1.0), 40.0);
+    assert_eq!(interpolate(&samples, 2, 0.5, 0.0), 5.0);
+    assert_eq!(interpolate(&samples, 2, 0.5, 0.5), 17.5);
+  }
+
+  #[test]
+  fn test_bicubic()
+  {
+    // a plane is reproduced exactly
+    let plane: Vec<f64> = (0..16)
+      .map(|i| 3.0 * (i % 4) as f64 - 2.0 * (i / 4) as f64)
+      .collect();
+    for (tx, ty) in [(0.0, 0.0), (0.25, 0.75), (0.5, 0.5), (1.0, 0.1)] {
+      let expected = 3.0 * (1.0 + tx) - 2.0 * (1.0 + ty);
+      assert!((interpolate(&plane, 4, tx, ty) - expected).abs() < 1e-9);
+    }
+
+    // the spline passes through the samples
+    let samples: Vec<f64> = (0..16).map(|i| ((i * 7) % 5) as f64).collect();
+    assert!((interpolate(&samples, 4, 0.0, 0.0) - samples[5]).abs() < 1e-9);
+    assert!((interpolate(&samples, 4, 1.0, 1.0) - samples[10]).abs() < 1e-9);
+  }
+
+  #[test]
+  fn test_geographic()
+  {
+    let position = RasterPosition {
+      level: 0,
+      size: (100, 200),
+      transform: GeoTransform([179.0, 0.01, 0.0, 61.0, 0.0, -0.005]),
+      pixel: (0.0, 0.0)
+    };
+    assert!(position.contains(99, 199));
+    assert!(!position.contains(-1, 0));
+    assert!(!position.contains(0, 200));
+    let (latitude, longitude) = position.geographic(-1, -1);
+    assert!((latitude - 61.0025).abs() < 1e-9);
+    assert!((longitude - 178.995).abs() < 1e-9);
+    assert!((position.geographic(100, 0).1 + 179.995).abs() < 1e-9);
+  }
+
+  #[test]
+  fn test_raster_position()
+  {
+    // 10 arc-second tile N75E030, where edge lengths of the tile differ the most
+    let n = 360;
+    let step = 1.0 / n as f64;
+    let samples: Vec<f32> = (0..n * n).map(|i| (i % n + 1000 * (i / n)) as f32).collect();
+    let buf = GeoTiffWriter::new(n, n, GeoTransform([30.0, step, 0.0, 76.0, 0.0, -step]))
+      .to_bytes(&Samples::Float32(samples))
+      .unwrap();
+    let tile = TileIdentity::from_geotiff(String::new(), GeoTiff::from_bytes(&buf).unwrap())
+      .unwrap();
+    let key = TileSignature::new(75, 30);
+    let pixel = |latitude: f64, longitude: f64| {
+      raster_position(&tile, key, (latitude, longitude), 0.0).unwrap().pixel
+    };
+    let value = |latitude: f64, longitude: f64| {
+      raster_position(&tile, key, (latitude, longitude), 0.0).unwrap().nearest(&tile).unwrap()
+    };
+
+    let (column, row) = pixel(75.5, 30.5);
+    assert!((column - 180.0).abs() < 1e-9 && (row - 180.0).abs() < 1e-9);
+    let (column, row) = pixel(75.75 + step / 2.0, 30.25 + step / 2.0);
+    assert!((column - 90.5).abs() < 1e-9 && (row - 89.5).abs() < 1e-9);
+    assert_eq!(value(76.0 - step / 2.0, 30.0 + step / 2.0), Some(0.0));
+    assert_eq!(value(75.5 - step / 2.0, 30.9 + step / 2.0), Some(324.0 + 180_000.0));
+    assert_eq!(value(75.0, 31.0 - step / 2.0), Some(359.0 + 359_000.0));
+    assert_eq!(value(75.0, 30.0), Some(359_000.0));
+  }
+
+  #[test]
+  fn test_elevations_at_invalid()
+  {
+    assert!(elevations_at(&[]).is_empty());
+    let elevations = elevations_at(&[(95.0, 0.0), (0.0, 181.0)]);
+    assert!(matches!(elevations[0], Err(Error::Positioning(_))));
+    assert!(matches!(elevations[1], Err(Error::Positioning(_))));
+  }
+}
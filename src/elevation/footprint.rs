@@ -0,0 +1,359 @@
+use std::iter::once;
+use json::{object, JsonValue};
+use log::{debug, info};
+use meridian_positioning::GeoCoordinate;
+use crate::elevation::line_of_sight::OPTICAL_REFRACTION;
+use crate::elevation::ray::{intersect_terrain, Attitude, Ray};
+use crate::errors::Error;
+use crate::utils::write_output;
+
+/// Number of rays cast along each edge of the image, its first corner included.
+const EDGE_RAYS: usize = 8;
+
+/// Names of the corners of a footprint, in the order they are stored.
+const CORNERS: [&str; 4] = ["top_left", "top_right", "bottom_right", "bottom_left"];
+
+/// Pinhole camera with square pixels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Camera
+{
+  /// Angle between the left and right edges of the image in degrees.
+  pub horizontal_fov: f32,
+  /// Width and height of the image in pixels.
+  pub resolution: (u32, u32)
+}
+
+impl Camera
+{
+  /// Distance of the image plane from the optical centre in pixels.
+  pub fn focal_length(&self) -> f64
+  {
+    self.resolution.0 as f64 / 2.0 / (self.horizontal_fov as f64 / 2.0).to_radians().tan()
+  }
+
+  /// Angle between the top and bottom edges of the image in degrees.
+  pub fn vertical_fov(&self) -> f32
+  {
+    (2.0 * (self.resolution.1 as f64 / 2.0 / self.focal_length()).atan()).to_degrees() as f32
+  }
+
+  /// Direction through a point of the image as forward, right and down
+  /// components, forward being the optical axis. Columns and rows are counted
+  /// from the top-left corner of the image.
+  fn direction(&self, column: f64, row: f64) -> [f64; 3]
+  {
+    let focal_length = self.focal_length();
+    [
+      1.0,
+      (column - self.resolution.0 as f64 / 2.0) / focal_length,
+      (row - self.resolution.1 as f64 / 2.0) / focal_length
+    ]
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FootprintPoint
+{
+  /// Column and row of the point in the image.
+  pub pixel: (f32, f32),
+  /// Where the ray through the pixel hits the terrain, with the elevation there.
+  /// Rays that do not reach the terrain within the range, such as those above
+  /// the horizon, end at the range under the ray without an altitude.
+  pub coordinate: GeoCoordinate,
+  /// Distance from the camera in meters, `None` when off the terrain.
+  pub slant_range: Option<f32>,
+  /// Ground sample distance in meters along the rows and along the columns of
+  /// the image, `None` when off the terrain.
+  pub ground_sample_distance: Option<(f32, f32)>
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Footprint
+{
+  pub camera: Camera,
+  pub position: GeoCoordinate,
+  pub attitude: Attitude,
+  /// Top-left, top-right, bottom-right and bottom-left corners of the image.
+  pub corners: Vec<FootprintPoint>,
+  pub center: FootprintPoint,
+  /// Points along the edges of the image, anticlockwise on the ground from the
+  /// top-left corner.
+  pub outline: Vec<FootprintPoint>
+}
+
+/// Projects the image of a camera at `position` onto the terrain. The camera
+/// looks along the forward axis of `attitude`, with the right and down axes
+/// along the rows and columns of the image. Rays are marched every `step` meters
+/// up to `max_range` meters from the camera along the ground. Ground sample
+/// distances are those of level ground at the elevation of each hit.
+pub fn camera_footprint(camera: &Camera, position: &GeoCoordinate, attitude: &Attitude,
+                        max_range: f32, step: f32) -> Result<Footprint, Error>
+{
+  // rays read few points, which is cheaper than decoding whole tiles for
+  // their bounds
+  footprint_with(camera, position, attitude, max_range,
+                 |ray| intersect_terrain(ray, max_range, step, OPTICAL_REFRACTION, false))
+}
+
+/// Same as [`camera_footprint`], finding where each ray hits the terrain with
+/// `intersect`.
+fn footprint_with(camera: &Camera, position: &GeoCoordinate, attitude: &Attitude, max_range: f32,
+                  mut intersect: impl FnMut(&Ray) -> Result<Option<GeoCoordinate>, Error>)
+  -> Result<Footprint, Error>
+{
+  if !(camera.horizontal_fov > 0.0 && camera.horizontal_fov < 180.0) {
+    return Err(Error::InvalidArgument(format!(
+      "camera field of view must be between 0 and 180 degrees, got {}", camera.horizontal_fov
+    )));
+  }
+  if camera.resolution.0 == 0 || camera.resolution.1 == 0 {
+    return Err(Error::InvalidArgument(format!("camera resolution is empty: {:?}",
+                                              camera.resolution)));
+  }
+
+  let (width, height) = (camera.resolution.0 as f32, camera.resolution.1 as f32);
+  let edges = [((0.0, 0.0), (0.0, height)), ((0.0, height), (width, height)),
+               ((width, height), (width, 0.0)), ((width, 0.0), (0.0, 0.0))];
+  let mut outline = Vec::with_capacity(edges.len() * EDGE_RAYS);
+  for (from, to) in edges {
+    for i in 0..EDGE_RAYS {
+      let t = i as f32 / EDGE_RAYS as f32;
+      let pixel = (from.0 + (to.0 - from.0) * t, from.1 + (to.1 - from.1) * t);
+      outline.push(project(camera, position, attitude, pixel, max_range, &mut intersect)?);
+    }
+  }
+  debug!("Camera footprint outlined by {} rays", outline.len());
+
+  Ok(Footprint {
+    camera: *camera,
+    position: *position,
+    attitude: *attitude,
+    corners: [0, 3, 2, 1]
+      .iter()
+      .map(|x| outline[x * EDGE_RAYS])
+      .collect(),
+    center: project(camera, position, attitude, (width / 2.0, height / 2.0), max_range,
+                    &mut intersect)?,
+    outline
+  })
+}
+
+impl Footprint
+{
+  /// Whether every ray of the outline hits the terrain.
+  pub fn is_complete(&self) -> bool
+  {
+    self.outline
+      .iter()
+      .all(|x| x.slant_range.is_some())
+  }
+
+  /// Feature collection with the polygon of the footprint followed by the points
+  /// of its corners and centre.
+  pub fn to_geojson(&self) -> JsonValue
+  {
+    let position = |x: &FootprintPoint| vec![x.coordinate.longitude, x.coordinate.latitude];
+    let mut ring: Vec<Vec<f64>> = self.outline
+      .iter()
+      .map(position)
+      .collect();
+    ring.push(ring[0].clone());
+    let mut features = vec![object! {
+      type: "Feature",
+      properties: {
+        horizontal_fov: self.camera.horizontal_fov,
+        vertical_fov: self.camera.vertical_fov(),
+        complete: self.is_complete()
+      },
+      geometry: {
+        type: "Polygon",
+        coordinates: vec![ring]
+      }
+    }];
+    let points = CORNERS
+      .iter()
+      .zip(&self.corners)
+      .chain(once((&"center", &self.center)));
+    for (name, point) in points {
+      let distance = point.ground_sample_distance;
+      features.push(object! {
+        type: "Feature",
+        properties: {
+          name: *name,
+          column: point.pixel.0,
+          row: point.pixel.1,
+          elevation: point.coordinate.altitude,
+          slant_range: point.slant_range,
+          gsd_row: distance.map(|x| x.0),
+          gsd_column: distance.map(|x| x.1)
+        },
+        geometry: {
+          type: "Point",
+          coordinates: position(point)
+        }
+      });
+    }
+    object! { type: "FeatureCollection", features: features }
+  }
+
+  /// Writes the footprint to `{target_path}.geojson`.
+  pub fn save(&self, target_path: &str) -> Result<(), Error>
+  {
+    let path = format!("{target_path}.geojson");
+    write_output(&path, self.to_geojson().dump().as_bytes())?;
+    info!("Camera footprint saved to {}", &path);
+    Ok(())
+  }
+}
+
+/// Casts the ray through `pixel` onto the terrain with `intersect`.
+fn project(camera: &Camera, position: &GeoCoordinate, attitude: &Attitude, pixel: (f32, f32),
+           max_range: f32,
+           intersect: &mut impl FnMut(&Ray) -> Result<Option<GeoCoordinate>, Error>)
+  -> Result<FootprintPoint, Error>
+{
+  let ray = Ray::from_attitude(position, attitude,
+                               camera.direction(pixel.0 as f64, pixel.1 as f64));
+  Ok(match intersect(&ray)? {
+    Some(hit) => {
+      let drop = (position.altitude.unwrap_or(0.0) - hit.altitude.unwrap_or(0.0)) as f64;
+      let slant_range = (position.distance_to(&hit)? as f64).hypot(drop);
+      FootprintPoint {
+        pixel,
+        coordinate: hit,
+        slant_range: Some(slant_range as f32),
+        ground_sample_distance: ground_sample_distance(camera, attitude, pixel, slant_range)
+      }
+    }
+    None => FootprintPoint {
+      pixel,
+      coordinate: GeoCoordinate {
+        altitude: None,
+        ..position.at_distance_and_azimuth(max_range, ray.azimuth)?
+      },
+      slant_range: None,
+      ground_sample_distance: None
+    }
+  })
+}
+
+/// Size on level ground of the pixel at `pixel`, seen `slant_range` meters away,
+/// along the rows and along the columns of the image. `None` when the ray does
+/// not look down.
+fn ground_sample_distance(camera: &Camera, attitude: &Attitude, pixel: (f32, f32),
+                          slant_range: f64) -> Option<(f32, f32)>
+{
+  let ray = attitude.to_local(camera.direction(pixel.0 as f64, pixel.1 as f64));
+  if ray[2] <= 0.0 {
+    return None;
+  }
+  let scale = slant_range / (ray[0] * ray[0] + ray[1] * ray[1] + ray[2] * ray[2]).sqrt();
+  // moving the ray by one pixel along an axis slides its hit on the ground by
+  let size = |axis: [f64; 3]| {
+    let axis = attitude.to_local(axis.map(|x| x / camera.focal_length()));
+    let along = axis[2] / ray[2];
+    (scale * (axis[0] - ray[0] * along).hypot(axis[1] - ray[1] * along)) as f32
+  };
+  Some((size([0.0, 1.0, 0.0]), size([0.0, 0.0, 1.0])))
+}
+
+#[cfg(test)]
+mod tests
+{
+  use super::*;
+
+  const CAMERA: Camera = Camera { horizontal_fov: 90.0, resolution: (1000, 500) };
+
+  #[test]
+  fn test_camera()
+  {
+    assert!((CAMERA.focal_length() - 500.0).abs() < 1e-9);
+    assert!((CAMERA.vertical_fov() - 2.0 * 0.5f32.atan().to_degrees()).abs() < 1e-4);
+    assert_eq!(CAMERA.direction(500.0, 250.0), [1.0, 0.0, 0.0]);
+    let corner = CAMERA.direction(0.0, 500.0);
+    assert!((corner[1] + 1.0).abs() < 1e-9 && (corner[2] - 0.5).abs() < 1e-9);
+  }
+
+  #[test]
+  fn test_ground_sample_distance()
+  {
+    // 100 m straight down, a pixel spans 1/500 of the distance both ways
+    let nadir = Attitude { yaw: 30.0, pitch: -90.0, roll: 0.0 };
+    let (row, column) = ground_sample_distance(&CAMERA, &nadir, (500.0, 250.0), 100.0).unwrap();
+    assert!((row - 0.2).abs() < 1e-5 && (column - 0.2).abs() < 1e-5);
+
+    // looking 45 degrees down, the columns stretch by 1 / sin(45)
+    let oblique = Attitude { yaw: 0.0, pitch: -45.0, roll: 0.0 };
+    let (row, column) = ground_sample_distance(&CAMERA, &oblique, (500.0, 250.0), 100.0).unwrap();
+    assert!((row - 0.2).abs() < 1e-5);
+    assert!((column - 0.2 * 2.0f32.sqrt()).abs() < 1e-5);
+
+    let level = Attitude::default();
+    assert_eq!(ground_sample_distance(&CAMERA, &level, (500.0, 0.0), 100.0), None);
+    assert!(ground_sample_distance(&CAMERA, &level, (500.0, 500.0), 100.0).is_some());
+  }
+
+  #[test]
+  fn test_horizon()
+  {
+    // pitched up 10 degrees 100 m over flat ground at sea level, the top of the
+    // image and its centre look above the horizon
+    let position = GeoCoordinate::new(60.0, 30.0, Some(100.0));
+    let attitude = Attitude { yaw: 30.0, pitch: 10.0, roll: 0.0 };
+    let flat = |ray: &Ray| {
+      let distance = 100.0 / (-ray.elevation).to_radians().tan();
+      match ray.elevation < 0.0 && distance <= 1000.0 {
+        true => Ok(Some(GeoCoordinate {
+          altitude: Some(0.0),
+          ..ray.origin.at_distance_and_azimuth(distance, ray.azimuth)?
+        })),
+        false => Ok(None)
+      }
+    };
+    let footprint = footprint_with(&CAMERA, &position, &attitude, 1000.0, flat).unwrap();
+    assert!(!footprint.is_complete());
+
+    for point in [&footprint.corners[0], &footprint.corners[1], &footprint.center] {
+      let ray = Ray::from_attitude(&position, &attitude,
+                                   CAMERA.direction(point.pixel.0 as f64, point.pixel.1 as f64));
+      let end = position.at_distance_and_azimuth(1000.0, ray.azimuth).unwrap();
+      assert_eq!((point.coordinate.latitude, point.coordinate.longitude),
+                 (end.latitude, end.longitude));
+      assert_eq!(point.coordinate.altitude, None);
+      assert_eq!(point.slant_range, None);
+      assert_eq!(point.ground_sample_distance, None);
+    }
+    for point in [&footprint.corners[2], &footprint.corners[3]] {
+      assert_eq!(point.coordinate.altitude, Some(0.0));
+      let distance = position.distance_to(&point.coordinate).unwrap();
+      assert!((point.slant_range.unwrap() - distance.hypot(100.0)).abs() < 1e-2);
+      assert!(point.ground_sample_distance.is_some());
+    }
+
+    let json = footprint.to_geojson();
+    assert_eq!(json["features"].len(), 6);
+    let polygon = &json["features"][0];
+    assert_eq!(polygon["properties"]["complete"], false);
+    let ring = &polygon["geometry"]["coordinates"][0];
+    assert_eq!(ring.len(), 4 * EDGE_RAYS + 1);
+    assert_eq!(ring[0], ring[4 * EDGE_RAYS]);
+    let top_left = &json["features"][1]["properties"];
+    assert_eq!(top_left["name"], "top_left");
+    assert!(top_left["elevation"].is_null() && top_left["slant_range"].is_null());
+    assert!(top_left["gsd_row"].is_null() && top_left["gsd_column"].is_null());
+    assert_eq!(json["features"][3]["properties"]["elevation"], 0.0);
+    assert_eq!(json["features"][5]["properties"]["name"], "center");
+  }
+
+  #[test]
+  fn test_camera_footprint_invalid()
+  {
+    let position = GeoCoordinate::new(60.0, 30.0, Some(100.0));
+    let camera = Camera { horizontal_fov: 180.0, ..CAMERA };
+    assert!(matches!(camera_footprint(&camera, &position, &Attitude::default(), 1000.0, 30.0),
+                     Err(Error::InvalidArgument(_))));
+    let camera = Camera { resolution: (0, 500), ..CAMERA };
+    assert!(matches!(camera_footprint(&camera, &position, &Attitude::default(), 1000.0, 30.0),
+                     Err(Error::InvalidArgument(_))));
+  }
+}
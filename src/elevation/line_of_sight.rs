@@ -0,0 +1,138 @@
+use meridian_positioning::GeoCoordinate;
+use crate::elevation::profile::{build_profile, ElevationPoint};
+use crate::errors::Error;
+
+/// Mean radius of the earth in meters.
+pub const EARTH_RADIUS: f64 = 6_371_008.8;
+
+/// Refraction coefficient of visible light in a standard atmosphere.
+pub const OPTICAL_REFRACTION: f32 = 0.13;
+
+/// Refraction coefficient of radio waves in a standard atmosphere, which bends
+/// them along an earth of 4/3 of its real radius.
+pub const RADIO_REFRACTION: f32 = 0.25;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LineOfSight
+{
+  pub visible: bool,
+  /// Terrain point closest to the start of the line that blocks it.
+  pub obstruction: Option<ElevationPoint>,
+  /// Smallest height of the line above the terrain between its ends in meters,
+  /// negative when the line is blocked. Infinite when no terrain was sampled
+  /// between the ends.
+  pub clearance: f32
+}
+
+/// Tests whether terrain blocks the straight line between two points whose
+/// altitudes are given above mean sea level. The terrain is sampled every
+/// `step` meters and raised by the bulge of an earth whose radius is enlarged
+/// by the `refraction` coefficient. Samples without elevation data never block
+/// the line.
+pub fn line_of_sight(from: &GeoCoordinate, to: &GeoCoordinate, refraction: f32, step: f32)
+  -> Result<LineOfSight, Error>
+{
+  let altitude = |coordinate: &GeoCoordinate| coordinate.altitude.ok_or(
+    Error::InvalidArgument(format!("line of sight end {} has no altitude", coordinate))
+  );
+  let altitudes = (altitude(from)?, altitude(to)?);
+  let profile = build_profile(&[*from, *to], step)?;
+  Ok(clearance(&profile, altitudes, refraction))
+}
+
+/// Radius of the earth as seen by a ray bent by `refraction`.
+pub fn effective_earth_radius(refraction: f32) -> f64
+{
+  EARTH_RADIUS / (1.0 - refraction as f64)
+}
+
+/// Height in meters the earth bulges above the chord between two points
+/// `length` meters apart, at `distance` meters from the first one.
+pub fn earth_bulge(distance: f32, length: f32, refraction: f32) -> f32
+{
+  (distance as f64 * (length - distance) as f64 / (2.0 * effective_earth_radius(refraction)))
+    as f32
+}
+
+fn clearance(profile: &[ElevationPoint], altitudes: (f32, f32), refraction: f32) -> LineOfSight
+{
+  let length = profile
+    .last()
+    .map_or(0.0, |x| x.distance);
+  let mut result = LineOfSight { visible: true, obstruction: None, clearance: f32::INFINITY };
+  for point in profile.iter().skip(1).take(profile.len().saturating_sub(2)) {
+    let elevation = match point.elevation {
+      Some(x) => x,
+      None => continue
+    };
+    let line = altitudes.0 + (altitudes.1 - altitudes.0) * point.distance / length;
+    let clearance = line - elevation - earth_bulge(point.distance, length, refraction);
+    result.clearance = result.clearance.min(clearance);
+    if clearance < 0.0 && result.visible {
+      result.visible = false;
+      result.obstruction = Some(*point);
+    }
+  }
+  result
+}
+
+#[cfg(test)]
+mod tests
+{
+  use super::*;
+
+  fn profile(elevations: &[Option<f32>], step: f32) -> Vec<ElevationPoint>
+  {
+    elevations
+      .iter()
+      .enumerate()
+      .map(|(i, elevation)| ElevationPoint {
+        latitude: 0.0,
+        longitude: 0.0,
+        distance: i as f32 * step,
+        elevation: *elevation
+      })
+      .collect()
+  }
+
+  #[test]
+  fn test_earth_bulge()
+  {
+    // about 7.85 m in the middle of 20 km without refraction
+    assert!((earth_bulge(10_000.0, 20_000.0, 0.0) - 7.848).abs() < 0.001);
+    assert_eq!(earth_bulge(0.0, 20_000.0, 0.0), 0.0);
+    assert!((earth_bulge(10_000.0, 20_000.0, RADIO_REFRACTION) - 7.848 * 0.75).abs() < 0.001);
+  }
+
+  #[test]
+  fn test_clearance_over_flat_earth_curvature()
+  {
+    let flat = profile(&[Some(0.0); 21], 1000.0);
+    let result = clearance(&flat, (10.0, 10.0), 0.0);
+    assert!(result.visible);
+    assert!((result.clearance - (10.0 - 7.848)).abs() < 0.001);
+
+    // too low to see over the bulge of 20 km of water
+    let result = clearance(&flat, (5.0, 5.0), 0.0);
+    assert!(!result.visible);
+    assert!((result.clearance - (5.0 - 7.848)).abs() < 0.001);
+
+    // but refraction lowers it enough
+    assert!(clearance(&flat, (5.0, 5.0), 0.5).visible);
+  }
+
+  #[test]
+  fn test_obstruction()
+  {
+    let elevations = [Some(100.0), Some(20.0), Some(70.0), None, Some(90.0), Some(100.0)];
+    let terrain = profile(&elevations, 10.0);
+    let result = clearance(&terrain, (110.0, 60.0), 0.0);
+    assert!(!result.visible);
+    assert_eq!(result.obstruction.unwrap().distance, 40.0);
+    assert!((result.clearance - (70.0 - 90.0)).abs() < 0.01);
+
+    let result = clearance(&terrain[..2], (0.0, 0.0), 0.0);
+    assert!(result.visible);
+    assert_eq!(result.clearance, f32::INFINITY);
+  }
+}
@@ -1,2 +1,8 @@
 pub mod elevation;
-pub mod profile;
\ No newline at end of file
+pub mod footprint;
+pub mod line_of_sight;
+pub mod profile;
+pub mod radar_coverage;
+pub mod ray;
+pub mod terrain;
+pub mod viewshed;
\ No newline at end of file
@@ -1,121 +1,151 @@
-// use crate::elevation_at;
-// use crate::errors::Error;
-// use crate::positioning::geocoordinate::GeoCoordinate;
-//
-// pub struct ElevationPoint
-// {
-//   pub distance: f32,
-//   pub elevation: f32
-// }
+use std::cmp::Ordering;
+use meridian_positioning::GeoCoordinate;
+use crate::elevation::elevation::elevations_at;
+use crate::errors::Error;
 
-// pub fn build_profile(path: Vec<GeoCoordinate>, discrete: u8) -> Result<Vec<ElevationPoint>, Error>
-// {
-//   if path.is_empty() {
-//     return Err(Error::EmptyPath);
-//   }
-//   let mut profile: Vec<ElevationPoint> = Vec::new();
-//   let mut distance_from_start = 0.0;
-//   let mut prev_base_point_geo = path.get(0).unwrap();
-//   for mut point in path {
-//     if !profile.is_empty() {
-//       let azimuth = prev_base_point_geo.azimuth_to(&point)?;
-//       let distance_from_prev_base_point = prev_base_point_geo.distance_to(&point)?;
-//       let mut distance = discrete as f32;
-//       let mut prev_delta_point_geo = prev_base_point_geo.clone();
-//       while distance < distance_from_prev_base_point {
-//         let mut delta_point_geo = prev_base_point_geo
-//           .at_distance_and_azimuth(distance, azimuth, 0.0)?;
-//         let new_altitude = elevation_at((delta_point_geo.latitude, delta_point_geo.longitude))?;
-//         delta_point_geo.altitude = new_altitude as f64;
-//
-//         if prev_delta_point_geo.altitude != delta_point_geo.altitude {
-//           if prev_delta_point_geo.altitude > delta_point_geo.altitude {
-//             profile.push(ElevationPoint {
-//               distance: distance - discrete as f32 + distance_from_start,
-//               elevation: prev_delta_point_geo.altitude as f32
-//             });
-//           }
-//           else {
-//             profile.push(ElevationPoint {
-//               distance: distance + distance_from_start,
-//               elevation: delta_point_geo.altitude as f32
-//             });
-//           }
-//         }
-//         prev_delta_point_geo = &delta_point_geo;
-//         distance += discrete as f32;
-//       }
-//       distance_from_start += distance_from_prev_base_point;
-//     }
-//     let new_altitude = elevation_at((point.latitude, point.longitude))?;
-//     point.altitude = new_altitude as f64;
-//     profile.push(ElevationPoint {
-//       distance: distance_from_start,
-//       elevation: point.altitude as f32
-//     });
-//     prev_base_point_geo = &point;
-//   }
-//
-//   Ok(profile)
-// }
-//
-// pub fn build_profile_as_geopath(path: Vec<GeoCoordinate>, step: f32) -> Result<Vec<GeoCoordinate>, Error>
-// {
-//   if path.is_empty() {
-//     return Err(Error::EmptyPath);
-//   }
-//   let mut profile: Vec<GeoCoordinate> = Vec::new();
-//   for mut point in path {
-//     if !profile.is_empty() {
-//       let previous = profile.last().unwrap();
-//       let azimuth = previous.azimuth_to(&point)?;
-//       let delta = previous.distance_to(&point)?;
-//       let delta2 = step;
-//       let t = previous.clone();
-//       while delta2 < delta {
-//         let u = previous.at_distance_and_azimuth(delta2, azimuth, 0.0)?;
-//         let alt = elevation_at((u.latitude, u.longitude))?;
-//         u.setAltitude(alt as f64);
-//         if t.altitude() != u.altitude() {
-//           if t.altitude() > u.altitude() {
-//             profile.push(t);
-//           }
-//         }
-//       }
-//     }
-//   }
-//   for(auto point : path.path())
-//   {
-//     if(ret.size())
-//     {
-//       auto previous = ret.coordinateAt(ret.size() - 1);
-//       auto azimuth = previous.azimuthTo(point);
-//       auto delta = previous.distanceTo(point);
-//       f32 delta2 = step;
-//       auto t = previous;
-//       while(delta2 < delta)
-//       {
-//         auto u = previous.atDistanceAndAzimuth(delta2, azimuth);
-//         auto alt = elevation(u);
-//         if(not alt.has_value())
-//         return ret;
-//         u.setAltitude(alt.value());
-//         if(t.altitude() != u.altitude())
-//         {
-//           if(t.altitude() > u.altitude()) ret.addCoordinate(t);
-//           else ret.addCoordinate(u);
-//         }
-//         t = u;
-//         delta2 += step;
-//       }
-//     }
-//     auto alt = elevation(point);
-//     if(not alt.has_value())
-//     return ret;
-//     point.setAltitude(alt.value());
-//     ret.addCoordinate(point);
-//   }
-//   return ret;
-// }
-//
-// }
\ No newline at end of file
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ElevationPoint
+{
+  pub latitude: f64,
+  pub longitude: f64,
+  /// Distance from the start of the path along its segments, in meters.
+  pub distance: f32,
+  /// `None` where there is no elevation data.
+  pub elevation: Option<f32>
+}
+
+/// Samples the elevation every `step` meters along the geodesic segments of
+/// `path`. Every vertex of the path is part of the profile.
+pub fn build_profile(path: &[GeoCoordinate], step: f32) -> Result<Vec<ElevationPoint>, Error>
+{
+  let samples = sample_path(path, step)?;
+  let coordinates: Vec<(f64, f64)> = samples
+    .iter()
+    .map(|(coordinate, _)| (coordinate.latitude, coordinate.longitude))
+    .collect();
+  Ok(samples
+    .iter()
+    .zip(elevations_at(&coordinates))
+    .map(|((coordinate, distance), elevation)| ElevationPoint {
+      latitude: coordinate.latitude,
+      longitude: coordinate.longitude,
+      distance: *distance,
+      elevation: elevation.ok()
+    })
+    .collect())
+}
+
+/// Keeps the ends of a profile and the points where the terrain stops rising,
+/// falling or staying level, which is enough to draw it without loss.
+pub fn inflection_points(profile: &[ElevationPoint]) -> Vec<ElevationPoint>
+{
+  let trend = |a: &ElevationPoint, b: &ElevationPoint| match (a.elevation, b.elevation) {
+    (Some(a), Some(b)) => b.partial_cmp(&a),
+    _ => None
+  };
+  let mut points: Vec<ElevationPoint> = profile
+    .windows(3)
+    .filter(|x| trend(&x[0], &x[1]) != trend(&x[1], &x[2])
+      || trend(&x[0], &x[1]).is_none())
+    .map(|x| x[1])
+    .collect();
+  if let Some(first) = profile.first() {
+    points.insert(0, *first);
+  }
+  if profile.len() > 1 {
+    points.push(profile[profile.len() - 1]);
+  }
+  points
+}
+
+/// Coordinates every `step` meters along the path and their distance from its
+/// start.
+fn sample_path(path: &[GeoCoordinate], step: f32) -> Result<Vec<(GeoCoordinate, f32)>, Error>
+{
+  let first = path.first().ok_or(Error::EmptyPath)?;
+  if step.partial_cmp(&0.0) != Some(Ordering::Greater) {
+    return Err(Error::InvalidArgument(format!("profile step must be positive, got {}", step)));
+  }
+
+  let mut samples = vec![(*first, 0.0)];
+  let mut distance_from_start = 0.0;
+  for segment in path.windows(2) {
+    let (start, end) = (&segment[0], &segment[1]);
+    let length = start.distance_to(end)?;
+    let azimuth = start.azimuth_to(end)?;
+    let mut distance = step;
+    while distance < length {
+      samples.push((start.at_distance_and_azimuth(distance, azimuth)?,
+                    distance_from_start + distance));
+      distance += step;
+    }
+    distance_from_start += length;
+    samples.push((*end, distance_from_start));
+  }
+
+  Ok(samples)
+}
+
+#[cfg(test)]
+mod tests
+{
+  use super::*;
+
+  fn point(distance: f32, elevation: Option<f32>) -> ElevationPoint
+  {
+    ElevationPoint { latitude: 0.0, longitude: 0.0, distance, elevation }
+  }
+
+  #[test]
+  fn test_sample_path()
+  {
+    let path = [
+      GeoCoordinate::new(60.0, 30.0, None),
+      GeoCoordinate::new(60.0, 30.01, None),
+      GeoCoordinate::new(60.0, 30.01, None),
+      GeoCoordinate::new(60.005, 30.01, None)
+    ];
+    let first = path[0].distance_to(&path[1]).unwrap();
+    let second = path[2].distance_to(&path[3]).unwrap();
+    let samples = sample_path(&path, 100.0).unwrap();
+
+    assert_eq!(samples.len(), 1 + (first / 100.0).ceil() as usize + 1
+      + (second / 100.0).ceil() as usize);
+    assert_eq!(samples[0], (path[0], 0.0));
+    assert_eq!(samples[1].1, 100.0);
+    assert!((samples[1].0.distance_to(&path[0]).unwrap() - 100.0).abs() < 0.01);
+    assert!(samples.windows(2).all(|x| x[0].1 <= x[1].1));
+    assert_eq!(samples.last().unwrap(), &(path[3], first + second));
+    assert_eq!(sample_path(&path[..1], 100.0).unwrap(), vec![(path[0], 0.0)]);
+  }
+
+  #[test]
+  fn test_sample_path_invalid()
+  {
+    assert!(matches!(sample_path(&[], 100.0), Err(Error::EmptyPath)));
+    let path = [GeoCoordinate::new(60.0, 30.0, None)];
+    assert!(matches!(sample_path(&path, 0.0), Err(Error::InvalidArgument(_))));
+    assert!(matches!(sample_path(&path, f32::NAN), Err(Error::InvalidArgument(_))));
+  }
+
+  #[test]
+  fn test_inflection_points()
+  {
+    let elevations = [10.0, 20.0, 30.0, 30.0, 30.0, 20.0, 25.0, 30.0];
+    let profile: Vec<ElevationPoint> = elevations
+      .iter()
+      .enumerate()
+      .map(|(i, x)| point(i as f32, Some(*x)))
+      .collect();
+    let distances: Vec<f32> = inflection_points(&profile)
+      .iter()
+      .map(|x| x.distance)
+      .collect();
+    assert_eq!(distances, vec![0.0, 2.0, 4.0, 5.0, 7.0]);
+
+    let profile = [point(0.0, Some(1.0)), point(1.0, None), point(2.0, Some(1.0))];
+    assert_eq!(inflection_points(&profile).len(), 3);
+    assert_eq!(inflection_points(&profile[..1]).len(), 1);
+    assert!(inflection_points(&[]).is_empty());
+  }
+}
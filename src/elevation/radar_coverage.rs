@@ -0,0 +1,339 @@
+use json::{object, JsonValue};
+use log::{debug, info};
+use meridian_positioning::GeoCoordinate;
+use crate::elevation::elevation::{elevation_at, elevations_at_resolution};
+use crate::elevation::line_of_sight::{effective_earth_radius, RADIO_REFRACTION};
+use crate::errors::Error;
+use crate::utils::write_output;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Radar
+{
+  pub position: GeoCoordinate,
+  /// Height of the antenna above the terrain in meters.
+  pub antenna_height: f32,
+  /// Lowest and highest elevation angles of the beam in degrees.
+  pub elevation_limits: (f32, f32),
+  /// Instrumented range in meters.
+  pub max_range: f32
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AltitudeReference
+{
+  AboveGround,
+  AboveSeaLevel
+}
+
+/// Altitude of the targets the coverage is computed for.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TargetLayer
+{
+  pub altitude: f32,
+  pub reference: AltitudeReference
+}
+
+/// Highest elevation angle of the terrain seen along an azimuth.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MaskingAngle
+{
+  pub azimuth: f32,
+  /// Elevation angle in degrees, or the lowest elevation limit of the radar
+  /// when no terrain rises above it.
+  pub angle: f32,
+  /// Distance of the terrain that masks the azimuth, zero when nothing does.
+  pub distance: f32
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayerCoverage
+{
+  pub layer: TargetLayer,
+  /// Covered intervals of range in meters along every azimuth.
+  pub ranges: Vec<Vec<(f32, f32)>>
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RadarCoverage
+{
+  pub radar: Radar,
+  /// Angle between neighbouring azimuths in degrees, the first one being north.
+  pub azimuth_step: f32,
+  pub masking: Vec<MaskingAngle>,
+  pub layers: Vec<LayerCoverage>
+}
+
+/// Computes where targets at every altitude layer can be seen by the radar.
+/// Terrain is sampled every `range_step` meters along rays `azimuth_step`
+/// degrees apart, over an earth of 4/3 of its radius to account for the
+/// refraction of radio waves. Samples without elevation data mask nothing and
+/// their above ground targets are not covered.
+pub fn radar_coverage(radar: &Radar, layers: &[TargetLayer], azimuth_step: f32, range_step: f32)
+  -> Result<RadarCoverage, Error>
+{
+  if !(azimuth_step > 0.0 && range_step > 0.0 && radar.max_range > 0.0) {
+    return Err(Error::InvalidArgument(format!(
+      "radar coverage steps and range must be positive, got {}, {} and {}",
+      azimuth_step, range_step, radar.max_range
+    )));
+  }
+  let azimuths = (360.0 / azimuth_step).round().max(1.0) as usize;
+  let samples = (radar.max_range / range_step).floor() as usize;
+  if samples == 0 {
+    return Err(Error::InvalidArgument(format!(
+      "radar range {} is shorter than the range step {}", radar.max_range, range_step
+    )));
+  }
+  debug!("Radar coverage over {} azimuths of {} samples", azimuths, samples);
+
+  let height = elevation_at((radar.position.latitude, radar.position.longitude))?
+    + radar.antenna_height;
+  let mut coordinates = Vec::with_capacity(azimuths * samples);
+  for azimuth in 0..azimuths {
+    for sample in 1..=samples {
+      let coordinate = radar.position
+        .at_distance_and_azimuth(sample as f32 * range_step, azimuth as f32 * azimuth_step)?;
+      coordinates.push((coordinate.latitude, coordinate.longitude));
+    }
+  }
+  let elevations: Vec<Option<f32>> = elevations_at_resolution(&coordinates, range_step)
+    .into_iter()
+    .map(|x| x.ok())
+    .collect();
+
+  let mut coverage = RadarCoverage {
+    radar: *radar,
+    azimuth_step,
+    masking: Vec::with_capacity(azimuths),
+    layers: layers
+      .iter()
+      .map(|layer| LayerCoverage { layer: *layer, ranges: Vec::with_capacity(azimuths) })
+      .collect()
+  };
+  for (azimuth, elevations) in elevations.chunks(samples).take(azimuths).enumerate() {
+    let ray = cover_ray(radar, height, layers, elevations, range_step);
+    coverage.masking.push(MaskingAngle { azimuth: azimuth as f32 * azimuth_step, ..ray.0 });
+    for (layer, ranges) in coverage.layers.iter_mut().zip(ray.1) {
+      layer.ranges.push(ranges);
+    }
+  }
+  Ok(coverage)
+}
+
+impl RadarCoverage
+{
+  /// Feature collection with a multipolygon of the covered sectors of every
+  /// layer.
+  pub fn to_geojson(&self) -> Result<JsonValue, Error>
+  {
+    let mut features = Vec::with_capacity(self.layers.len());
+    for layer in &self.layers {
+      let mut polygons = Vec::new();
+      for (azimuth, ranges) in layer.ranges.iter().enumerate() {
+        let azimuth = azimuth as f32 * self.azimuth_step;
+        for range in ranges {
+          polygons.push(vec![self.sector(azimuth, *range)?]);
+        }
+      }
+      features.push(object! {
+        type: "Feature",
+        properties: {
+          altitude: layer.layer.altitude,
+          reference: match layer.layer.reference {
+            AltitudeReference::AboveGround => "AGL",
+            AltitudeReference::AboveSeaLevel => "AMSL"
+          }
+        },
+        geometry: {
+          type: "MultiPolygon",
+          coordinates: polygons
+        }
+      });
+    }
+    Ok(object! { type: "FeatureCollection", features: features })
+  }
+
+  /// Masking angle of every azimuth.
+  pub fn masking_diagram(&self) -> JsonValue
+  {
+    object! {
+      radar: {
+        latitude: self.radar.position.latitude,
+        longitude: self.radar.position.longitude,
+        antenna_height: self.radar.antenna_height
+      },
+      masking: self.masking
+        .iter()
+        .map(|x| object! { azimuth: x.azimuth, angle: x.angle, distance: x.distance })
+        .collect::<Vec<_>>()
+    }
+  }
+
+  /// Writes the coverage to `{target_path}.geojson` and the masking angle
+  /// diagram to `{target_path}.json`.
+  pub fn save(&self, target_path: &str) -> Result<(), Error>
+  {
+    let path = format!("{target_path}.geojson");
+    write_output(&path, self.to_geojson()?.dump().as_bytes())?;
+    write_output(&format!("{target_path}.json"), self.masking_diagram().pretty(4).as_bytes())?;
+    info!("Radar coverage saved to {}", &path);
+    Ok(())
+  }
+
+  /// Closed ring of `[longitude, latitude]` around the part of an azimuth
+  /// sector between two ranges.
+  fn sector(&self, azimuth: f32, range: (f32, f32)) -> Result<Vec<Vec<f64>>, Error>
+  {
+    let position = &self.radar.position;
+    let point = |distance: f32, azimuth: f32| -> Result<Vec<f64>, Error> {
+      let x = position.at_distance_and_azimuth(distance, azimuth)?;
+      Ok(vec![x.longitude, x.latitude])
+    };
+    let (left, right) = (azimuth - self.azimuth_step / 2.0, azimuth + self.azimuth_step / 2.0);
+    let mut ring = vec![point(range.0, left)?];
+    if range.0 > 0.0 {
+      ring.push(point(range.0, right)?);
+    }
+    ring.push(point(range.1, right)?);
+    ring.push(point(range.1, left)?);
+    ring.push(ring[0].clone());
+    Ok(ring)
+  }
+}
+
+/// Elevation angle in degrees of a point `height` meters above sea level and
+/// `distance` meters away from a radar at `radar_height`, over an earth of
+/// `earth_radius`.
+fn elevation_angle(height: f32, distance: f32, radar_height: f32, earth_radius: f64) -> f32
+{
+  let distance = distance as f64;
+  let drop = distance * distance / (2.0 * earth_radius);
+  (height as f64 - drop - radar_height as f64)
+    .atan2(distance)
+    .to_degrees() as f32
+}
+
+/// Masking angle and covered ranges of every layer along one ray of terrain
+/// `elevations` sampled every `step` meters from the radar.
+fn cover_ray(radar: &Radar, height: f32, layers: &[TargetLayer], elevations: &[Option<f32>],
+             step: f32) -> (MaskingAngle, Vec<Vec<(f32, f32)>>)
+{
+  let earth_radius = effective_earth_radius(RADIO_REFRACTION);
+  let (lowest, highest) = radar.elevation_limits;
+  let mut masking = MaskingAngle { azimuth: 0.0, angle: lowest, distance: 0.0 };
+  let mut ranges = vec![Vec::new(); layers.len()];
+  for (sample, elevation) in elevations.iter().enumerate() {
+    let distance = (sample + 1) as f32 * step;
+    for (layer, ranges) in layers.iter().zip(ranges.iter_mut()) {
+      let target = match (layer.reference, elevation) {
+        (AltitudeReference::AboveSeaLevel, _) => Some(layer.altitude),
+        (AltitudeReference::AboveGround, Some(elevation)) => Some(elevation + layer.altitude),
+        (AltitudeReference::AboveGround, None) => None
+      };
+      let covered = target.is_some_and(|target| {
+        let angle = elevation_angle(target, distance, height, earth_radius);
+        target >= elevation.unwrap_or(f32::MIN) && angle >= masking.angle && angle <= highest
+      });
+      let (start, end) = ((distance - step / 2.0).max(0.0), (distance + step / 2.0)
+        .min(radar.max_range));
+      match ranges.last_mut() {
+        Some((_, last)) if covered && *last >= start => *last = end,
+        _ if covered => ranges.push((start, end)),
+        _ => ()
+      }
+    }
+    if let Some(elevation) = elevation {
+      let angle = elevation_angle(*elevation, distance, height, earth_radius);
+      if angle > masking.angle {
+        masking.angle = angle;
+        masking.distance = distance;
+      }
+    }
+  }
+  (masking, ranges)
+}
+
+#[cfg(test)]
+mod tests
+{
+  use super::*;
+
+  fn radar(max_range: f32) -> Radar
+  {
+    Radar {
+      position: GeoCoordinate::new(60.0, 30.0, None),
+      antenna_height: 10.0,
+      elevation_limits: (-5.0, 30.0),
+      max_range
+    }
+  }
+
+  #[test]
+  fn test_radar_horizon()
+  {
+    // a 10 m antenna sees 1000 m high targets over the sea up to about 143 km
+    let earth_radius = effective_earth_radius(RADIO_REFRACTION);
+    let horizon = ((2.0 * earth_radius * 10.0).sqrt() + (2.0 * earth_radius * 1000.0).sqrt())
+      as f32;
+    let layers = [TargetLayer { altitude: 1000.0, reference: AltitudeReference::AboveSeaLevel }];
+    let (masking, ranges) = cover_ray(&radar(200_000.0), 10.0, &layers, &[Some(0.0); 200],
+                                      1000.0);
+    assert_eq!(ranges[0].len(), 1);
+    // closer targets are above the 30 degree beam
+    assert_eq!(ranges[0][0].0, 1500.0);
+    assert!((ranges[0][0].1 - horizon).abs() < 1000.0);
+    // the sea masks the radar at the horizon of the antenna
+    assert!((masking.distance - (2.0 * earth_radius * 10.0).sqrt() as f32).abs() < 1000.0);
+    assert!(masking.angle < 0.0 && masking.angle > -5.0);
+  }
+
+  #[test]
+  fn test_terrain_masking()
+  {
+    // a 110 m hill 5 km away hides low targets behind it
+    let mut elevations = [Some(0.0); 20];
+    elevations[4] = Some(110.0);
+    elevations[10] = None;
+    let layers = [
+      TargetLayer { altitude: 50.0, reference: AltitudeReference::AboveGround },
+      TargetLayer { altitude: 2000.0, reference: AltitudeReference::AboveSeaLevel }
+    ];
+    let (masking, ranges) = cover_ray(&radar(19_800.0), 10.0, &layers, &elevations, 1000.0);
+    assert!((masking.angle - elevation_angle(110.0, 5000.0, 10.0,
+                                             effective_earth_radius(RADIO_REFRACTION))).abs()
+      < 1e-6);
+    assert!(masking.angle > 1.0);
+    assert_eq!(masking.distance, 5000.0);
+    assert_eq!(ranges[0], vec![(500.0, 5500.0)]);
+    // high targets are covered from where they leave the beam to the end of the range
+    assert_eq!(ranges[1], vec![(3500.0, 19_800.0)]);
+  }
+
+  #[test]
+  fn test_range_shorter_than_step()
+  {
+    assert!(matches!(radar_coverage(&radar(500.0), &[], 10.0, 1000.0),
+                     Err(Error::InvalidArgument(_))));
+  }
+
+  #[test]
+  fn test_geojson()
+  {
+    let coverage = RadarCoverage {
+      radar: radar(1000.0),
+      azimuth_step: 90.0,
+      masking: vec![],
+      layers: vec![LayerCoverage {
+        layer: TargetLayer { altitude: 10.0, reference: AltitudeReference::AboveGround },
+        ranges: vec![vec![(0.0, 1000.0)], vec![], vec![(100.0, 200.0), (500.0, 600.0)], vec![]]
+      }]
+    };
+    let json = coverage.to_geojson().unwrap();
+    let geometry = &json["features"][0]["geometry"];
+    assert_eq!(json["features"][0]["properties"]["reference"], "AGL");
+    assert_eq!(geometry["coordinates"].len(), 3);
+    assert_eq!(geometry["coordinates"][0][0].len(), 4);
+    assert_eq!(geometry["coordinates"][1][0].len(), 5);
+    assert_eq!(geometry["coordinates"][1][0][0], geometry["coordinates"][1][0][4]);
+  }
+}
@@ -0,0 +1,209 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use meridian_positioning::GeoCoordinate;
+use crate::elevation::elevation::{elevation_at_interpolated, tile_elevation_range, Interpolation};
+use crate::elevation::line_of_sight::effective_earth_radius;
+use crate::errors::Error;
+use crate::tile_storage::TileSignature;
+
+/// Distance in meters along the ray to which crossings of the terrain are found.
+const PRECISION: f64 = 0.01;
+
+/// Orientation of a body in degrees. Its forward, right and down axes start
+/// along north, east and down and are turned by yaw, then pitch, then roll.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Attitude
+{
+  /// Clockwise from north.
+  pub yaw: f32,
+  /// Nose up.
+  pub pitch: f32,
+  /// Right side down.
+  pub roll: f32
+}
+
+impl Attitude
+{
+  /// Turns a direction given by its forward, right and down components in the
+  /// body into its north, east and down components.
+  pub fn to_local(&self, direction: [f64; 3]) -> [f64; 3]
+  {
+    let (sy, cy) = (self.yaw as f64).to_radians().sin_cos();
+    let (sp, cp) = (self.pitch as f64).to_radians().sin_cos();
+    let (sr, cr) = (self.roll as f64).to_radians().sin_cos();
+    let [x, y, z] = direction;
+    [
+      cp * cy * x + (sr * sp * cy - cr * sy) * y + (cr * sp * cy + sr * sy) * z,
+      cp * sy * x + (sr * sp * sy + cr * cy) * y + (cr * sp * sy - sr * cy) * z,
+      -sp * x + sr * cp * y + cr * cp * z
+    ]
+  }
+}
+
+/// Straight half-line from a point above the terrain.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ray
+{
+  /// Start of the ray, with its altitude above mean sea level.
+  pub origin: GeoCoordinate,
+  /// Degrees clockwise from north.
+  pub azimuth: f32,
+  /// Degrees above the horizontal plane, negative when looking down.
+  pub elevation: f32
+}
+
+impl Ray
+{
+  /// Ray along `direction` in the body frame of `attitude`, such as `[1, 0, 0]`
+  /// for the optical axis of a camera.
+  pub fn from_attitude(origin: &GeoCoordinate, attitude: &Attitude, direction: [f64; 3]) -> Self
+  {
+    let [north, east, down] = attitude.to_local(direction);
+    Self {
+      origin: *origin,
+      azimuth: (east.atan2(north).to_degrees() as f32).rem_euclid(360.0),
+      elevation: (-down).atan2(north.hypot(east)).to_degrees() as f32
+    }
+  }
+}
+
+/// Finds where the ray first hits the terrain within `max_range` meters of its
+/// origin along the ground. The ray is marched in steps of `step` meters over an
+/// earth whose radius is enlarged by the `refraction` coefficient, and the step
+/// it crosses the terrain in is bisected. With `tile_bounds` the terrain is not
+/// read while the ray stays above the highest point of the tile under it, at the
+/// cost of decoding every tile it crosses on its first use, which pays off for
+/// long rays over many points. Points without elevation data
+/// never stop the ray.
+///
+/// The hit has the altitude of the terrain there, and is `None` when the ray
+/// reaches `max_range` above the terrain.
+pub fn intersect_terrain(ray: &Ray, max_range: f32, step: f32, refraction: f32, tile_bounds: bool)
+  -> Result<Option<GeoCoordinate>, Error>
+{
+  let altitude = ray.origin.altitude.ok_or(
+    Error::InvalidArgument(format!("ray origin {} has no altitude", ray.origin))
+  )? as f64;
+  if step.partial_cmp(&0.0) != Some(Ordering::Greater) {
+    return Err(Error::InvalidArgument(format!("ray step must be positive, got {}", step)));
+  }
+
+  let slope = (ray.elevation as f64).to_radians().tan();
+  let earth_diameter = 2.0 * effective_earth_radius(refraction);
+  let height = |distance: f64| altitude + distance * slope + distance * distance / earth_diameter;
+  let mut ranges = HashMap::new();
+  let mut hit = None;
+  let distance = first_crossing(max_range as f64, step as f64, |distance, refine| {
+    let coordinate = ray.origin.at_distance_and_azimuth(distance as f32, ray.azimuth)?;
+    let coord = (coordinate.latitude, coordinate.longitude);
+    if tile_bounds && !refine {
+      let range = *ranges
+        .entry(TileSignature::from_f64(coord.0, coord.1))
+        .or_insert_with(|| tile_elevation_range(coord).ok().flatten());
+      if range.is_none_or(|(_, max)| height(distance) > max as f64) {
+        return Ok(None);
+      }
+    }
+    Ok(elevation_at_interpolated(coord, 0.0, Interpolation::Bilinear)
+      .ok()
+      .map(|elevation| {
+        hit = Some(GeoCoordinate::new(coord.0, coord.1, Some(elevation)));
+        height(distance) - elevation as f64
+      }))
+  })?;
+  Ok(distance.and(hit))
+}
+
+/// Smallest distance up to `max_range` where `clearance` drops to zero or below,
+/// sampled every `step` and then bisected. `clearance` gets the distance and
+/// whether the crossing is being refined, and gives `None` where it is unknown,
+/// which counts as clear. The last call is made at the returned distance.
+fn first_crossing(max_range: f64, step: f64,
+                  mut clearance: impl FnMut(f64, bool) -> Result<Option<f64>, Error>)
+  -> Result<Option<f64>, Error>
+{
+  let blocked = |x: Option<f64>| x.is_some_and(|x| x <= 0.0);
+  if blocked(clearance(0.0, false)?) {
+    return Ok(Some(0.0));
+  }
+  let mut clear = 0.0;
+  while clear < max_range {
+    let distance = (clear + step).min(max_range);
+    if !blocked(clearance(distance, false)?) {
+      clear = distance;
+      continue;
+    }
+
+    let mut crossing = distance;
+    while crossing - clear > PRECISION {
+      let middle = (clear + crossing) / 2.0;
+      match blocked(clearance(middle, true)?) {
+        true => crossing = middle,
+        false => clear = middle
+      }
+    }
+    // the caller keeps what it saw at the last call
+    clearance(crossing, true)?;
+    return Ok(Some(crossing));
+  }
+  Ok(None)
+}
+
+#[cfg(test)]
+mod tests
+{
+  use super::*;
+
+  fn crossing(max_range: f64, step: f64, clearance: impl Fn(f64) -> Option<f64>)
+    -> Option<f64>
+  {
+    first_crossing(max_range, step, |distance, _| Ok(clearance(distance))).unwrap()
+  }
+
+  #[test]
+  fn test_attitude()
+  {
+    let ray = |yaw: f32, pitch: f32, roll: f32, direction: [f64; 3]| {
+      let ray = Ray::from_attitude(&GeoCoordinate::new(0.0, 0.0, None),
+                                   &Attitude { yaw, pitch, roll }, direction);
+      (ray.azimuth, ray.elevation)
+    };
+    let close = |a: (f32, f32), b: (f32, f32)| (a.0 - b.0).abs() < 1e-4 && (a.1 - b.1).abs() < 1e-4;
+    assert!(close(ray(0.0, 0.0, 0.0, [1.0, 0.0, 0.0]), (0.0, 0.0)));
+    assert!(close(ray(90.0, -30.0, 0.0, [1.0, 0.0, 0.0]), (90.0, -30.0)));
+    assert!(close(ray(-90.0, 0.0, 0.0, [1.0, 0.0, 0.0]), (270.0, 0.0)));
+    assert!(close(ray(0.0, 0.0, 30.0, [0.0, 1.0, 0.0]), (90.0, -30.0)));
+    // a camera looking down from a pitched or banked aircraft
+    assert!((ray(45.0, 0.0, 0.0, [0.0, 0.0, 1.0]).1 + 90.0).abs() < 1e-4);
+    assert!(close(ray(0.0, 10.0, 0.0, [0.0, 0.0, 1.0]), (0.0, -80.0)));
+    assert!(close(ray(0.0, 0.0, 10.0, [0.0, 0.0, 1.0]), (270.0, -80.0)));
+  }
+
+  #[test]
+  fn test_first_crossing()
+  {
+    // looking down at 45 degrees from 100 m over flat ground
+    let hit = crossing(1000.0, 30.0, |x| Some(100.0 - x)).unwrap();
+    assert!((hit - 100.0).abs() <= PRECISION);
+    assert!(crossing(90.0, 30.0, |x| Some(100.0 - x)).is_none());
+    assert_eq!(crossing(1000.0, 30.0, |x| Some(-1.0 - x)), Some(0.0));
+
+    // a wall thinner than the step is missed, unknown terrain is clear
+    let wall = |x: f64| Some(if (40.0..45.0).contains(&x) { -1.0 } else { 1.0 });
+    assert!(crossing(100.0, 30.0, wall).is_none());
+    assert!((crossing(100.0, 5.0, wall).unwrap() - 40.0).abs() <= PRECISION);
+    assert!(crossing(1000.0, 30.0, |x| (x > 50.0).then_some(100.0 - x)).is_some());
+    assert!(crossing(1000.0, 30.0, |x| (x < 50.0).then_some(100.0 - x)).is_none());
+  }
+
+  #[test]
+  fn test_intersect_terrain_invalid()
+  {
+    let ray = Ray { origin: GeoCoordinate::new(60.0, 30.0, None), azimuth: 0.0, elevation: -45.0 };
+    assert!(matches!(intersect_terrain(&ray, 1000.0, 30.0, 0.0, false),
+                     Err(Error::InvalidArgument(_))));
+    let ray = Ray { origin: GeoCoordinate::new(60.0, 30.0, Some(100.0)), ..ray };
+    assert!(matches!(intersect_terrain(&ray, 1000.0, 0.0, 0.0, false),
+                     Err(Error::InvalidArgument(_))));
+  }
+}
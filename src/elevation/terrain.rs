@@ -0,0 +1,228 @@
+use std::cmp::Ordering;
+use std::io::Cursor;
+use image::{GrayImage, ImageFormat, Luma};
+use log::info;
+use meridian_positioning::{CardinalDirection, GeoCoordinate, GeoRectangle};
+use crate::errors::Error;
+use crate::heightmap::sample_row;
+use crate::utils::write_output;
+
+/// Local shape of the terrain, from the 3x3 window of elevations around a point.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TerrainDerivatives
+{
+  /// Steepest slope in degrees.
+  pub slope: f32,
+  /// Direction the slope faces in degrees clockwise from north, `None` on level
+  /// ground.
+  pub aspect: Option<f32>,
+  /// Curvature in 1/m, positive on convex terrain such as ridges and hilltops
+  /// and negative in valleys.
+  pub curvature: f32
+}
+
+/// Direction of the light of a hillshade.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sun
+{
+  /// Degrees clockwise from north.
+  pub azimuth: f32,
+  /// Degrees above the horizon.
+  pub altitude: f32
+}
+
+impl Default for Sun
+{
+  /// Light from the north-west, the cartographic convention.
+  fn default() -> Self
+  {
+    Self { azimuth: 315.0, altitude: 45.0 }
+  }
+}
+
+impl TerrainDerivatives
+{
+  /// Steepest slope as the percentage of rise over run.
+  pub fn slope_percent(&self) -> f32
+  {
+    self.slope.to_radians().tan() * 100.0
+  }
+
+  /// Brightness of the terrain lit by `sun`, from 0 for shadow to 1 for terrain
+  /// facing the sun.
+  pub fn hillshade(&self, sun: &Sun) -> f32
+  {
+    let zenith = (90.0 - sun.altitude).to_radians();
+    let slope = self.slope.to_radians();
+    let facing = self.aspect.map_or(0.0, |aspect| (sun.azimuth - aspect).to_radians().cos());
+    (zenith.cos() * slope.cos() + zenith.sin() * slope.sin() * facing).clamp(0.0, 1.0)
+  }
+}
+
+/// Derivatives of the terrain grid over a georectangle.
+#[derive(Debug, Clone)]
+pub struct TerrainGrid
+{
+  pub georectangle: GeoRectangle,
+  /// Number of points along each side of the grid.
+  pub size: usize,
+  /// Row-major derivatives from the north-western point, `None` next to voids.
+  pub cells: Vec<Option<TerrainDerivatives>>
+}
+
+impl TerrainGrid
+{
+  /// Writes the hillshade of the grid to `{target_path}.png`, black where it is
+  /// unknown.
+  pub fn save_hillshade(&self, target_path: &str, sun: &Sun) -> Result<(), Error>
+  {
+    let path = format!("{target_path}.png");
+    let image = GrayImage::from_fn(self.size as u32, self.size as u32, |column, row| {
+      let cell = self.cells[row as usize * self.size + column as usize];
+      Luma([cell.map_or(0, |x| (x.hillshade(sun) * u8::MAX as f32).round() as u8)])
+    });
+    let mut png = Cursor::new(Vec::new());
+    image.write_to(&mut png, ImageFormat::Png)?;
+    write_output(&path, png.get_ref())?;
+    info!("Hillshade saved to {}", &path);
+    Ok(())
+  }
+}
+
+/// Derivatives of the terrain at a coordinate, from elevations `spacing` meters
+/// apart around it.
+pub fn terrain_at(coordinate: (f64, f64), spacing: f32) -> Result<TerrainDerivatives, Error>
+{
+  if spacing.partial_cmp(&0.0) != Some(Ordering::Greater) {
+    return Err(Error::InvalidArgument(format!("spacing must be positive, got {}", spacing)));
+  }
+  let first = GeoCoordinate::new(coordinate.0, coordinate.1, None)
+    .at_distance_and_azimuth(spacing, CardinalDirection::North.to_degrees())?
+    .at_distance_and_azimuth(spacing, CardinalDirection::West.to_degrees())?;
+  let window = sample_grid(&first, 3, (spacing, spacing), spacing)?;
+  window_derivatives(&window, 3, 1, 1, (spacing, spacing))
+    .ok_or(Error::NoData(coordinate.0, coordinate.1))
+}
+
+/// Derivatives of the terrain at `size` x `size` points over a georectangle,
+/// laid out like the heightmaps of `convert_georectangle`.
+pub fn terrain_grid(georectangle: GeoRectangle, size: usize) -> Result<TerrainGrid, Error>
+{
+  if size == 0 {
+    return Err(Error::InvalidArgument("terrain grid must have points".to_string()));
+  }
+  let spacing = (georectangle.width_meters()? / size as f32,
+                 georectangle.height_meters()? / size as f32);
+  // one more point on every side for the windows of the edges
+  let first = georectangle.top_left()
+    .at_distance_and_azimuth(spacing.1, CardinalDirection::North.to_degrees())?
+    .at_distance_and_azimuth(spacing.0, CardinalDirection::West.to_degrees())?;
+  let elevations = sample_grid(&first, size + 2, spacing, spacing.0.min(spacing.1))?;
+  let cells = (0..size * size)
+    .map(|i| window_derivatives(&elevations, size + 2, i / size + 1, i % size + 1, spacing))
+    .collect();
+  Ok(TerrainGrid { georectangle, size, cells })
+}
+
+/// Elevations of `size` x `size` points from the north-western `first`, rows
+/// `spacing.1` meters apart southwards and columns `spacing.0` apart eastwards.
+fn sample_grid(first: &GeoCoordinate, size: usize, spacing: (f32, f32), resolution: f32)
+  -> Result<Vec<Option<f32>>, Error>
+{
+  let mut elevations = Vec::with_capacity(size * size);
+  for row in 0..size {
+    let start = first
+      .at_distance_and_azimuth(row as f32 * spacing.1, CardinalDirection::South.to_degrees())?;
+    elevations.extend(sample_row(&start, size, spacing.0, resolution)?);
+  }
+  Ok(elevations)
+}
+
+/// Derivatives by Horn's method over the 3x3 window centred on `(row, column)`
+/// of a row-major grid of `width` columns.
+fn window_derivatives(elevations: &[Option<f32>], width: usize, row: usize, column: usize,
+                      spacing: (f32, f32)) -> Option<TerrainDerivatives>
+{
+  let mut z = [0.0f64; 9];
+  for (i, z) in z.iter_mut().enumerate() {
+    *z = elevations[(row + i / 3 - 1) * width + column + i % 3 - 1]? as f64;
+  }
+  let (dx, dy) = (spacing.0 as f64, spacing.1 as f64);
+  let east = ((z[2] + 2.0 * z[5] + z[8]) - (z[0] + 2.0 * z[3] + z[6])) / (8.0 * dx);
+  let north = ((z[0] + 2.0 * z[1] + z[2]) - (z[6] + 2.0 * z[7] + z[8])) / (8.0 * dy);
+  let gradient = east.hypot(north);
+  let laplacian = (z[3] + z[5] - 2.0 * z[4]) / (dx * dx) + (z[1] + z[7] - 2.0 * z[4]) / (dy * dy);
+  Some(TerrainDerivatives {
+    slope: gradient.atan().to_degrees() as f32,
+    aspect: (gradient > 0.0)
+      .then(|| ((-east).atan2(-north).to_degrees() as f32).rem_euclid(360.0)),
+    curvature: -laplacian as f32
+  })
+}
+
+#[cfg(test)]
+mod tests
+{
+  use super::*;
+
+  fn derivatives(elevation: impl Fn(f32, f32) -> f32) -> TerrainDerivatives
+  {
+    // 3x3 window of 10 m, x eastwards and y northwards from its centre
+    let window: Vec<Option<f32>> = (0..9)
+      .map(|i| Some(elevation((i % 3) as f32 * 10.0 - 10.0, 10.0 - (i / 3) as f32 * 10.0)))
+      .collect();
+    window_derivatives(&window, 3, 1, 1, (10.0, 10.0)).unwrap()
+  }
+
+  #[test]
+  fn test_plane()
+  {
+    // rising 10 m every 100 m eastwards, so facing west
+    let plane = derivatives(|x, _| 100.0 + x / 10.0);
+    assert!((plane.slope_percent() - 10.0).abs() < 1e-4);
+    assert!((plane.slope - 5.7106).abs() < 1e-3);
+    assert!((plane.aspect.unwrap() - 270.0).abs() < 1e-3);
+    assert!(plane.curvature.abs() < 1e-6);
+
+    let plane = derivatives(|x, y| x + y);
+    assert!((plane.slope - 2.0f32.sqrt().atan().to_degrees()).abs() < 1e-3);
+    assert!((plane.aspect.unwrap() - 225.0).abs() < 1e-3);
+
+    let flat = derivatives(|_, _| 42.0);
+    assert_eq!(flat.slope, 0.0);
+    assert_eq!(flat.aspect, None);
+  }
+
+  #[test]
+  fn test_curvature()
+  {
+    let hill = derivatives(|x, y| 100.0 - (x * x + y * y) / 100.0);
+    assert!((hill.curvature - 0.04).abs() < 1e-6);
+    assert_eq!(hill.slope, 0.0);
+    let valley = derivatives(|x, _| x * x / 100.0);
+    assert!((valley.curvature + 0.02).abs() < 1e-6);
+  }
+
+  #[test]
+  fn test_hillshade()
+  {
+    let sun = Sun::default();
+    let flat = derivatives(|_, _| 0.0);
+    assert!((flat.hillshade(&sun) - 45.0f32.to_radians().cos()).abs() < 1e-6);
+
+    // a 45 degree slope facing the sun is fully lit, facing away it is dark
+    let towards = derivatives(|x, y| (x - y) / 2.0f32.sqrt());
+    assert!((towards.aspect.unwrap() - 315.0).abs() < 1e-3);
+    assert!((towards.hillshade(&sun) - 1.0).abs() < 1e-6);
+    let away = derivatives(|x, y| (y - x) / 2.0f32.sqrt());
+    assert_eq!(away.hillshade(&sun), 0.0);
+  }
+
+  #[test]
+  fn test_voids()
+  {
+    let mut window = vec![Some(0.0); 9];
+    window[8] = None;
+    assert_eq!(window_derivatives(&window, 3, 1, 1, (10.0, 10.0)), None);
+  }
+}
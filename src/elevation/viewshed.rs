@@ -0,0 +1,248 @@
+use std::io::Cursor;
+use image::{GrayImage, ImageFormat, Luma};
+use json::object;
+use log::{debug, info};
+use meridian_positioning::{CardinalDirection, GeoCoordinate, GeoRectangle};
+use crate::elevation::elevation::elevations_at_resolution;
+use crate::elevation::line_of_sight::effective_earth_radius;
+use crate::errors::Error;
+use crate::utils::write_output;
+
+/// Horizon of cells with no terrain between them and the observer.
+const NO_HORIZON: f64 = -1.0e9;
+
+/// Visibility of the cells of a square grid centred on an observer, aligned
+/// with the cardinal directions.
+#[derive(Debug, Clone)]
+pub struct Viewshed
+{
+  pub observer: GeoCoordinate,
+  /// Number of cells along each side of the grid, which is odd.
+  pub size: usize,
+  /// Length of the side of a cell in meters.
+  pub cell_size: f32,
+  pub radius: f32,
+  /// Outer corners of the grid.
+  pub bounds: GeoRectangle,
+  /// Row-major visibility of cells, from the north-western one.
+  pub visible: Vec<bool>
+}
+
+impl Viewshed
+{
+  pub fn is_visible(&self, column: usize, row: usize) -> bool
+  {
+    column < self.size && row < self.size && self.visible[row * self.size + column]
+  }
+
+  /// Writes the visibility as a `{target_path}.png` mask, white where visible,
+  /// and the bounds of the grid to `{target_path}.json`.
+  pub fn save(&self, target_path: &str) -> Result<(), Error>
+  {
+    let path = format!("{target_path}.png");
+    let image = GrayImage::from_fn(self.size as u32, self.size as u32, |column, row| {
+      Luma([if self.is_visible(column as usize, row as usize) { u8::MAX } else { 0 }])
+    });
+    let mut png = Cursor::new(Vec::new());
+    image.write_to(&mut png, ImageFormat::Png)?;
+    write_output(&path, png.get_ref())?;
+    info!("Viewshed mask saved to {}", &path);
+
+    let json = object!
+    {
+      viewshed:
+      {
+        observer:
+        {
+          latitude: self.observer.latitude,
+          longitude: self.observer.longitude
+        },
+        radius: self.radius,
+        size: self.size,
+        cell_size: self.cell_size,
+        bounds:
+        {
+          north: self.bounds.top_left().latitude,
+          west: self.bounds.top_left().longitude,
+          south: self.bounds.bottom_right().latitude,
+          east: self.bounds.bottom_right().longitude
+        }
+      }
+    };
+    write_output(&format!("{target_path}.json"), json
+      .pretty(4)
+      .as_bytes())?;
+    Ok(())
+  }
+}
+
+/// Computes which cells of `cell_size` meters within `radius` meters of the
+/// observer can see, or be seen from, the observer. The observer stands
+/// `antenna_height` meters above the terrain and the targets `target_height`
+/// meters above it. The grid is swept ring by ring outwards, each cell taking
+/// its horizon from the two cells of the previous ring its line of sight passes
+/// between. Cells without elevation data are not visible and block nothing.
+pub fn viewshed(observer: &GeoCoordinate, antenna_height: f32, target_height: f32, radius: f32,
+                cell_size: f32, refraction: f32) -> Result<Viewshed, Error>
+{
+  if !(radius > 0.0 && cell_size > 0.0) {
+    return Err(Error::InvalidArgument(format!(
+      "viewshed radius and cell size must be positive, got {} and {}", radius, cell_size
+    )));
+  }
+  let cells = (radius / cell_size).ceil() as usize;
+  let size = 2 * cells + 1;
+  debug!("Viewshed of {}x{} cells of {} m around {}", size, size, cell_size, observer);
+
+  let offset = |coordinate: &GeoCoordinate, cells: f32, direction: CardinalDirection| {
+    coordinate.at_distance_and_azimuth(cells * cell_size, direction.to_degrees())
+  };
+  let mut elevations = Vec::with_capacity(size * size);
+  for row in 0..size {
+    let base = offset(observer, cells as f32 - row as f32, CardinalDirection::North)?;
+    let mut coordinates = Vec::with_capacity(size);
+    for column in 0..size {
+      let coordinate = offset(&base, column as f32 - cells as f32, CardinalDirection::East)?;
+      coordinates.push((coordinate.latitude, coordinate.longitude));
+    }
+    elevations.extend(elevations_at_resolution(&coordinates, cell_size)
+      .into_iter()
+      .map(|x| x.ok()));
+  }
+  let ground = elevations[cells * size + cells]
+    .ok_or(Error::NoData(observer.latitude, observer.longitude))?;
+
+  let half = cells as f32 + 0.5;
+  let top_left = offset(&offset(observer, half, CardinalDirection::North)?, half,
+                        CardinalDirection::West)?;
+  let bottom_right = offset(&offset(observer, half, CardinalDirection::South)?, half,
+                            CardinalDirection::East)?;
+  Ok(Viewshed {
+    observer: *observer,
+    size,
+    cell_size,
+    radius,
+    bounds: GeoRectangle::new(top_left, bottom_right),
+    visible: sweep(&elevations, cells, cell_size, ground + antenna_height, target_height, radius,
+                   refraction)
+  })
+}
+
+/// Visibility of the `2 * cells + 1` square grid of `elevations` around an
+/// observer at `eye` meters above sea level in its middle.
+fn sweep(elevations: &[Option<f32>], cells: usize, cell_size: f32, eye: f32, target_height: f32,
+         radius: f32, refraction: f32) -> Vec<bool>
+{
+  let size = 2 * cells + 1;
+  let n = cells as i64;
+  let index = |x: i64, y: i64| ((n - y) * size as i64 + x + n) as usize;
+  let distance = |x: i64, y: i64| ((x * x + y * y) as f64).sqrt() * cell_size as f64;
+  let earth_diameter = 2.0 * effective_earth_radius(refraction);
+  // slope from the eye to a height above the terrain, lowered by the curvature
+  let slope = |x: i64, y: i64, height: f32| {
+    let distance = distance(x, y);
+    elevations[index(x, y)].map(|elevation| {
+      (elevation as f64 + height as f64 - distance * distance / earth_diameter - eye as f64)
+        / distance
+    })
+  };
+
+  let mut visible = vec![false; size * size];
+  let mut horizon = vec![NO_HORIZON; size * size];
+  visible[index(0, 0)] = elevations[index(0, 0)].is_some();
+  for ring in 1..=n {
+    // the line to the observer crosses the previous ring between two cells
+    let crossing = |along: i64| along as f64 * (ring - 1) as f64 / ring as f64;
+    for (x, y) in ring_cells(ring) {
+      let (low, high, t) = match x.abs() == ring {
+        true => {
+          let (x, y) = (x - x.signum(), crossing(y));
+          (index(x, y.floor() as i64), index(x, y.ceil() as i64), y - y.floor())
+        }
+        false => {
+          let (x, y) = (crossing(x), y - y.signum());
+          (index(x.floor() as i64, y), index(x.ceil() as i64, y), x - x.floor())
+        }
+      };
+      let blocking = horizon[low] + (horizon[high] - horizon[low]) * t;
+      visible[index(x, y)] = distance(x, y) <= radius as f64
+        && slope(x, y, target_height).is_some_and(|x| x >= blocking);
+      horizon[index(x, y)] = slope(x, y, 0.0).map_or(blocking, |x| x.max(blocking));
+    }
+  }
+  visible
+}
+
+/// Offsets of the cells `ring` cells away from the centre in Chebyshev distance.
+fn ring_cells(ring: i64) -> impl Iterator<Item = (i64, i64)>
+{
+  (-ring..=ring)
+    .flat_map(move |x| (-ring..=ring).map(move |y| (x, y)))
+    .filter(move |(x, y)| x.abs() == ring || y.abs() == ring)
+}
+
+#[cfg(test)]
+mod tests
+{
+  use super::*;
+
+  fn grid(cells: usize, elevation: impl Fn(i64, i64) -> Option<f32>) -> Vec<Option<f32>>
+  {
+    let n = cells as i64;
+    (0..2 * n + 1)
+      .flat_map(|row| (0..2 * n + 1).map(move |column| (column - n, n - row)))
+      .map(|(x, y)| elevation(x, y))
+      .collect()
+  }
+
+  #[test]
+  fn test_ring_cells()
+  {
+    assert_eq!(ring_cells(1).count(), 8);
+    assert_eq!(ring_cells(3).count(), 24);
+    assert!(ring_cells(2).all(|(x, y)| x.abs().max(y.abs()) == 2));
+  }
+
+  #[test]
+  fn test_flat()
+  {
+    let elevations = grid(10, |_, _| Some(100.0));
+    let visible = sweep(&elevations, 10, 10.0, 102.0, 0.0, 100.0, 0.0);
+    for (i, visible) in visible.iter().enumerate() {
+      let (x, y) = ((i % 21) as f64 - 10.0, 10.0 - (i / 21) as f64);
+      assert_eq!(*visible, (x * x + y * y).sqrt() <= 10.0, "cell {}, {}", x, y);
+    }
+  }
+
+  #[test]
+  fn test_wall()
+  {
+    // a 50 m wall 3 cells east of the observer, from 2 cells south to 2 north
+    let elevations = grid(10, |x, y| match (x, y) {
+      (3, -2..=2) => Some(50.0),
+      (0, 9) => None,
+      _ => Some(0.0)
+    });
+    let visible = sweep(&elevations, 10, 10.0, 2.0, 0.0, 1000.0, 0.0);
+    let at = |x: i64, y: i64| visible[((10 - y) * 21 + x + 10) as usize];
+    assert!(at(3, 0) && at(3, 2));
+    assert!(!at(4, 0) && !at(10, 0) && !at(8, 3) && !at(10, -4));
+    assert!(at(-10, 0) && at(10, 10) && at(0, -10) && at(3, 4));
+    assert!(!at(0, 9) && at(0, 10));
+
+    // a high enough target is seen over the wall
+    let visible = sweep(&elevations, 10, 10.0, 2.0, 200.0, 1000.0, 0.0);
+    assert!(visible[10 * 21 + 20]);
+  }
+
+  #[test]
+  fn test_curvature()
+  {
+    // 2 m above a flat sea the horizon is about 5 km away
+    let elevations = grid(10, |_, _| Some(0.0));
+    let visible = sweep(&elevations, 10, 1000.0, 2.0, 0.0, 20_000.0, 0.0);
+    assert!(visible[10 * 21 + 15]);
+    assert!(!visible[10 * 21 + 16]);
+    assert!(!visible[10 * 21 + 20]);
+  }
+}
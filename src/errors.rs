@@ -7,17 +7,19 @@ pub enum Error
 {
   #[error("Not implemented")] NotImplemented,
   #[error("No such tile: {0}")] NoSuchTile(TileSignature),
+  #[error("No elevation data at ({0}, {1})")] NoData(f64, f64),
+  #[error("No raster data in {0}")] NoRaster(String),
   #[error("Network status code: {0} for signature {1}")] NetworkStatusCodeError(u16, TileSignature),
   #[error("Network status code: {0} - {1}")] NetworkStatusCodeErrorStr(u16, String),
   #[error("No such object in remote: {0}")] NoSuchObjectInRemote(TileSignature),
   #[error("Invalid quarter directory specifier: {0}")] InvalidQuarterDirectorySpecifier(String),
   #[error("Missing key: {0}")] ConfigMissingKey(String),
   #[error("Invalid argument: {0}")] InvalidArgument(String),
+  #[error("Path has no points")] EmptyPath,
 
   #[error(transparent)] Request(#[from] reqwest::Error),
   #[error(transparent)] Image(#[from] image::ImageError),
   #[error(transparent)] Tiff(#[from] TiffParserError),
-  #[error(transparent)] ImageSize(#[from] imagesize::ImageError),
   #[error(transparent)] Positioning(#[from] meridian_positioning::errors::PositioningError),
   #[error(transparent)] Io(#[from] std::io::Error),
   #[error(transparent)] ParallelDownloaderError(#[from] parallel_downloader::DownloaderError)
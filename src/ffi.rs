@@ -4,6 +4,8 @@ use std::env;
 use std::ffi::{c_char, c_double, c_float, c_int, CString};
 use meridian_positioning::{GeoCoordinate, GeoRectangle};
 use num_traits::FromPrimitive;
+use crate::elevation::elevation::Interpolation;
+use crate::elevation::profile;
 use crate::heightmap::{convert_georectangle, ImageFormat, Resolution};
 use crate::tile_storage::STORAGE;
 
@@ -23,6 +25,23 @@ pub struct MeridianVersion
   pub patch: c_int
 }
 
+#[repr(C)]
+pub struct MeridianProfilePoint
+{
+  pub latitude: c_double,
+  pub longitude: c_double,
+  pub distance: c_float,
+  pub elevation: c_float
+}
+
+/// Array of profile points owned by Rust, freed with `meridian_free_profile`.
+#[repr(C)]
+pub struct MeridianProfile
+{
+  pub points: *mut MeridianProfilePoint,
+  pub count: usize
+}
+
 #[no_mangle]
 #[allow(dead_code)]
 pub extern fn meridian_version() -> MeridianVersion
@@ -51,6 +70,125 @@ pub extern fn meridian_elevation(latitude: c_double, longitude: c_double) -> c_i
   }
 }
 
+/// Returns NaN when there is no elevation data at the coordinate or
+/// `interpolation` is not one of the `Interpolation` variants.
+#[no_mangle]
+#[allow(dead_code)]
+pub extern fn meridian_elevation_interpolated(latitude: c_double, longitude: c_double,
+  interpolation: c_int) -> c_float
+{
+  let interpolation = match Interpolation::from_i32(interpolation) {
+    Some(x) => x,
+    None => return c_float::NAN
+  };
+  match elevation::elevation::elevation_at_interpolated((latitude, longitude), 0.0,
+                                                        interpolation) {
+    Ok(value) => value as c_float,
+    Err(_) => c_float::NAN
+  }
+}
+
+/// Number of values in `count` latitude and longitude pairs, `None` when they
+/// can't be addressed as one slice.
+fn coordinate_values(count: usize) -> Option<usize>
+{
+  count
+    .checked_mul(2)
+    .filter(|values| *values <= isize::MAX as usize / size_of::<c_double>())
+}
+
+/// Reads the elevations of `count` coordinates, given as latitude and longitude
+/// pairs in `coordinates`, into `elevations`. Points without elevation data are
+/// set to NaN. Returns the number of points with elevation data.
+#[no_mangle]
+#[allow(dead_code)]
+pub extern fn meridian_elevations(coordinates: *const c_double, count: usize,
+  elevations: *mut c_float) -> usize
+{
+  if count == 0 || coordinates.is_null() || elevations.is_null() {
+    return 0;
+  }
+  let values = match coordinate_values(count) {
+    Some(x) => x,
+    None => return 0
+  };
+  let (coordinates, elevations) = unsafe {
+    (std::slice::from_raw_parts(coordinates, values),
+     std::slice::from_raw_parts_mut(elevations, count))
+  };
+  let coordinates: Vec<(f64, f64)> = coordinates
+    .chunks_exact(2)
+    .map(|x| (x[0], x[1]))
+    .collect();
+  let mut valid = 0;
+  for (target, elevation) in elevations
+    .iter_mut()
+    .zip(elevation::elevation::elevations_at(&coordinates))
+  {
+    *target = match elevation {
+      Ok(value) => {
+        valid += 1;
+        value as c_float
+      }
+      Err(_) => c_float::NAN
+    };
+  }
+  valid
+}
+
+/// Builds the profile of `count` vertices, given as latitude and longitude pairs
+/// in `path`, sampled every `step` meters. Elevations without data are NaN.
+/// Returns an empty profile on invalid arguments.
+#[no_mangle]
+#[allow(dead_code)]
+pub extern fn meridian_build_profile(path: *const c_double, count: usize, step: c_float,
+  inflections_only: bool) -> MeridianProfile
+{
+  let empty = MeridianProfile { points: std::ptr::null_mut(), count: 0 };
+  if path.is_null() {
+    return empty;
+  }
+  let values = match coordinate_values(count) {
+    Some(x) => x,
+    None => return empty
+  };
+  let path: Vec<GeoCoordinate> = unsafe { std::slice::from_raw_parts(path, values) }
+    .chunks_exact(2)
+    .map(|x| GeoCoordinate::new(x[0], x[1], None))
+    .collect();
+  let mut profile = match profile::build_profile(&path, step) {
+    Ok(x) => x,
+    Err(_) => return empty
+  };
+  if inflections_only {
+    profile = profile::inflection_points(&profile);
+  }
+  let points: Box<[MeridianProfilePoint]> = profile
+    .iter()
+    .map(|x| MeridianProfilePoint {
+      latitude: x.latitude,
+      longitude: x.longitude,
+      distance: x.distance,
+      elevation: x.elevation.unwrap_or(c_float::NAN)
+    })
+    .collect();
+  MeridianProfile {
+    count: points.len(),
+    points: Box::into_raw(points) as *mut MeridianProfilePoint
+  }
+}
+
+#[no_mangle]
+#[allow(dead_code)]
+pub extern fn meridian_free_profile(profile: MeridianProfile)
+{
+  if !profile.points.is_null() {
+    unsafe {
+      drop(Box::from_raw(std::ptr::slice_from_raw_parts_mut(profile.points, profile.count)));
+    }
+  }
+}
+
 #[no_mangle]
 #[allow(dead_code)]
 pub extern fn meridian_enable_logger() -> bool
@@ -106,6 +244,32 @@ mod tests
   {
     meridian_enable_logger();
   }
+  #[test]
+  fn test_build_profile_invalid()
+  {
+    let profile = meridian_build_profile(std::ptr::null(), 0, 10.0, false);
+    assert!(profile.points.is_null());
+    assert_eq!(profile.count, 0);
+    meridian_free_profile(profile);
+    let path = [60.0, 30.0];
+    let profile = meridian_build_profile(path.as_ptr(), 1, -1.0, false);
+    assert_eq!(profile.count, 0);
+    let profile = meridian_build_profile(path.as_ptr(), usize::MAX, 10.0, false);
+    assert!(profile.points.is_null());
+    assert_eq!(profile.count, 0);
+  }
+
+  #[test]
+  fn test_elevations_invalid()
+  {
+    let mut elevations = [0.0; 1];
+    assert_eq!(meridian_elevations(std::ptr::null(), 1, elevations.as_mut_ptr()), 0);
+    let coordinates = [60.0, 30.0];
+    assert_eq!(meridian_elevations(coordinates.as_ptr(), usize::MAX, elevations.as_mut_ptr()), 0);
+    assert_eq!(meridian_elevations(coordinates.as_ptr(), usize::MAX / 4, elevations.as_mut_ptr()),
+               0);
+  }
+
   #[test]
   fn test_elevation_at()
   {
@@ -0,0 +1,308 @@
+use super::TiffParserError;
+use super::parser::Ifd;
+
+pub(super) const TAG_MODEL_PIXEL_SCALE: u16 = 33550;
+pub(super) const TAG_MODEL_TIEPOINT: u16 = 33922;
+pub(super) const TAG_MODEL_TRANSFORMATION: u16 = 34264;
+pub(super) const TAG_GEO_KEY_DIRECTORY: u16 = 34735;
+const TAG_GEO_DOUBLE_PARAMS: u16 = 34736;
+pub(super) const TAG_GEO_ASCII_PARAMS: u16 = 34737;
+
+pub(super) const KEY_MODEL_TYPE: u16 = 1024;
+pub(super) const KEY_RASTER_TYPE: u16 = 1025;
+const KEY_CITATION: u16 = 1026;
+pub(super) const KEY_GEOGRAPHIC_TYPE: u16 = 2048;
+pub(super) const KEY_GEOG_CITATION: u16 = 2049;
+pub(super) const KEY_GEOG_ANGULAR_UNITS: u16 = 2054;
+const KEY_PROJECTED_CS_TYPE: u16 = 3072;
+
+const USER_DEFINED: u16 = 32767;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ModelType {
+  Projected,
+  Geographic,
+  Geocentric,
+}
+
+/// Whether the georeferenced coordinate of a pixel is its top-left corner
+/// (GeoTIFF default) or its center.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RasterType {
+  PixelIsArea,
+  PixelIsPoint,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GeoKeyValue {
+  Short(u16),
+  Doubles(Vec<f64>),
+  Ascii(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeoKey {
+  pub id: u16,
+  pub value: GeoKeyValue,
+}
+
+/// Affine transform from raster (column, row) to model (x, y) space, with
+/// coefficients in GDAL order: `x = c[0] + col * c[1] + row * c[2]`,
+/// `y = c[3] + col * c[4] + row * c[5]`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GeoTransform(pub [f64; 6]);
+
+impl GeoTransform {
+  pub fn from_tiepoint_and_scale(tiepoint: &[f64], scale: &[f64]) -> Self {
+    let (i, j, x, y) = (tiepoint[0], tiepoint[1], tiepoint[3], tiepoint[4]);
+    let (sx, sy) = (scale[0], scale[1]);
+    Self([x - i * sx, sx, 0.0, y + j * sy, 0.0, -sy])
+  }
+
+  pub fn from_model_transformation(matrix: &[f64]) -> Self {
+    Self([matrix[3], matrix[0], matrix[1], matrix[7], matrix[4], matrix[5]])
+  }
+
+  pub fn pixel_to_model(&self, column: f64, row: f64) -> (f64, f64) {
+    let c = &self.0;
+    (c[0] + column * c[1] + row * c[2], c[3] + column * c[4] + row * c[5])
+  }
+
+  pub fn model_to_pixel(&self, x: f64, y: f64) -> Option<(f64, f64)> {
+    let c = &self.0;
+    let det = c[1] * c[5] - c[2] * c[4];
+    if det == 0.0 {
+      return None;
+    }
+    let (dx, dy) = (x - c[0], y - c[3]);
+    Some(((dx * c[5] - dy * c[2]) / det, (dy * c[1] - dx * c[4]) / det))
+  }
+
+  /// Returns the transform shifted by `(column, row)` pixels.
+  pub fn translated(&self, column: f64, row: f64) -> Self {
+    let (x, y) = self.pixel_to_model(column, row);
+    let c = &self.0;
+    Self([x, c[1], c[2], y, c[4], c[5]])
+  }
+
+  /// Returns the transform of pixels `columns` by `rows` times larger.
+  pub fn scaled(&self, columns: f64, rows: f64) -> Self {
+    let c = &self.0;
+    Self([c[0], c[1] * columns, c[2] * rows, c[3], c[4] * columns, c[5] * rows])
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Georeference {
+  pub transform: GeoTransform,
+  pub model_type: Option<ModelType>,
+  pub raster_type: RasterType,
+  pub epsg: Option<u16>,
+  pub citation: Option<String>,
+  pub keys: Vec<GeoKey>,
+}
+
+impl Georeference {
+  /// Reads georeferencing tags from an IFD. Returns `None` if the image is
+  /// not georeferenced.
+  pub fn from_ifd(ifd: &Ifd) -> Result<Option<Self>, TiffParserError> {
+    let transform = if ifd.has_entry(TAG_MODEL_TRANSFORMATION) {
+      let matrix = ifd.get_value(TAG_MODEL_TRANSFORMATION)?;
+      if matrix.doubles()?.len() < 16 {
+        return Err(TiffParserError::InvalidValue(matrix.clone(), "expected 4x4 matrix"));
+      }
+      GeoTransform::from_model_transformation(matrix.doubles()?)
+    } else if ifd.has_entry(TAG_MODEL_TIEPOINT) && ifd.has_entry(TAG_MODEL_PIXEL_SCALE) {
+      let tiepoint = ifd.get_value(TAG_MODEL_TIEPOINT)?;
+      let scale = ifd.get_value(TAG_MODEL_PIXEL_SCALE)?;
+      if tiepoint.doubles()?.len() < 6 {
+        return Err(TiffParserError::InvalidValue(tiepoint.clone(), "expected tiepoint"));
+      }
+      if scale.doubles()?.len() < 2 {
+        return Err(TiffParserError::InvalidValue(scale.clone(), "expected pixel scale"));
+      }
+      GeoTransform::from_tiepoint_and_scale(tiepoint.doubles()?, scale.doubles()?)
+    } else {
+      return Ok(None);
+    };
+
+    let keys = if ifd.has_entry(TAG_GEO_KEY_DIRECTORY) {
+      read_geo_keys(ifd)?
+    } else {
+      vec![]
+    };
+    let short_key = |id: u16| {
+      keys
+        .iter()
+        .find(|key| key.id == id)
+        .and_then(|key| match key.value {
+          GeoKeyValue::Short(x) => Some(x),
+          _ => None,
+        })
+    };
+
+    let model_type = match short_key(KEY_MODEL_TYPE) {
+      Some(1) => Some(ModelType::Projected),
+      Some(2) => Some(ModelType::Geographic),
+      Some(3) => Some(ModelType::Geocentric),
+      _ => None,
+    };
+    let raster_type = match short_key(KEY_RASTER_TYPE) {
+      Some(2) => RasterType::PixelIsPoint,
+      _ => RasterType::PixelIsArea,
+    };
+    let epsg_key = match model_type {
+      Some(ModelType::Projected) => KEY_PROJECTED_CS_TYPE,
+      _ => KEY_GEOGRAPHIC_TYPE,
+    };
+    let epsg = short_key(epsg_key).filter(|code| *code != USER_DEFINED);
+    let citation = keys
+      .iter()
+      .find(|key| key.id == KEY_CITATION)
+      .and_then(|key| match &key.value {
+        GeoKeyValue::Ascii(x) => Some(x.clone()),
+        _ => None,
+      });
+
+    Ok(Some(Self {
+      transform,
+      model_type,
+      raster_type,
+      epsg,
+      citation,
+      keys,
+    }))
+  }
+
+  /// Transform whose origin is the outer corner of the first pixel, whatever
+  /// the raster type of the file is.
+  pub fn corner_transform(&self) -> GeoTransform {
+    match self.raster_type {
+      RasterType::PixelIsArea => self.transform,
+      RasterType::PixelIsPoint => self.transform.translated(-0.5, -0.5),
+    }
+  }
+
+  /// Outer bounds of a `width` x `height` raster as (west, south, east, north).
+  pub fn bounds(&self, width: usize, height: usize) -> (f64, f64, f64, f64) {
+    let transform = self.corner_transform();
+    let corners = [
+      transform.pixel_to_model(0.0, 0.0),
+      transform.pixel_to_model(width as f64, 0.0),
+      transform.pixel_to_model(0.0, height as f64),
+      transform.pixel_to_model(width as f64, height as f64),
+    ];
+    corners.iter().fold(
+      (f64::MAX, f64::MAX, f64::MIN, f64::MIN),
+      |b, c| (b.0.min(c.0), b.1.min(c.1), b.2.max(c.0), b.3.max(c.1))
+    )
+  }
+}
+
+fn read_geo_keys(ifd: &Ifd) -> Result<Vec<GeoKey>, TiffParserError> {
+  let directory = ifd.get_value(TAG_GEO_KEY_DIRECTORY)?;
+  let shorts = directory.shorts()?;
+  if shorts.len() < 4 || shorts.len() < 4 + shorts[3] as usize * 4 {
+    return Err(TiffParserError::InvalidValue(directory.clone(), "expected geo key directory"));
+  }
+  let doubles = match ifd.get_value(TAG_GEO_DOUBLE_PARAMS) {
+    Ok(x) => x.doubles()?,
+    Err(_) => &[],
+  };
+  let ascii = match ifd.get_value(TAG_GEO_ASCII_PARAMS) {
+    Ok(x) => x.ascii()?,
+    Err(_) => "",
+  };
+
+  shorts[4..4 + shorts[3] as usize * 4]
+    .chunks(4)
+    .map(|key| {
+      let (id, location, count, offset) = (key[0], key[1], key[2] as usize, key[3] as usize);
+      let value = match location {
+        0 => Some(GeoKeyValue::Short(key[3])),
+        TAG_GEO_KEY_DIRECTORY => shorts
+          .get(offset)
+          .map(|x| GeoKeyValue::Short(*x)),
+        TAG_GEO_DOUBLE_PARAMS => doubles
+          .get(offset..offset + count)
+          .map(|x| GeoKeyValue::Doubles(x.to_vec())),
+        TAG_GEO_ASCII_PARAMS => ascii
+          .get(offset..offset + count)
+          .map(|x| GeoKeyValue::Ascii(x.trim_end_matches('|').to_string())),
+        _ => None,
+      };
+      value
+        .map(|value| GeoKey { id, value })
+        .ok_or(TiffParserError::InvalidValue(directory.clone(), "geo key out of range"))
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::geotiff::parser::testing::TiffBuilder;
+  use crate::geotiff::TiffFile;
+
+  #[test]
+  fn test_srtm_tiepoint() {
+    // 1 arc-second SRTM tile N60E030, pixel-is-point, WGS 84
+    let step = 1.0 / 3600.0;
+    let buf = TiffBuilder::new()
+      .doubles(TAG_MODEL_PIXEL_SCALE, &[step, step, 0.0])
+      .doubles(TAG_MODEL_TIEPOINT, &[0.0, 0.0, 0.0, 30.0, 61.0, 0.0])
+      .shorts(TAG_GEO_KEY_DIRECTORY, &[
+        1, 1, 0, 4,
+        KEY_MODEL_TYPE, 0, 1, 2,
+        KEY_RASTER_TYPE, 0, 1, 2,
+        KEY_GEOGRAPHIC_TYPE, 0, 1, 4326,
+        KEY_CITATION, TAG_GEO_ASCII_PARAMS, 7, 0,
+      ])
+      .ascii(TAG_GEO_ASCII_PARAMS, "WGS 84|")
+      .build();
+    let tiff = TiffFile::from_bytes(&buf).unwrap();
+    let georeference = Georeference::from_ifd(&tiff.ifds[0]).unwrap().unwrap();
+
+    assert_eq!(georeference.model_type, Some(ModelType::Geographic));
+    assert_eq!(georeference.raster_type, RasterType::PixelIsPoint);
+    assert_eq!(georeference.epsg, Some(4326));
+    assert_eq!(georeference.citation.as_deref(), Some("WGS 84"));
+
+    let (west, south, east, north) = georeference.bounds(3601, 3601);
+    assert!((west - (30.0 - step / 2.0)).abs() < 1e-12);
+    assert!((north - (61.0 + step / 2.0)).abs() < 1e-12);
+    assert!((east - (31.0 + step / 2.0)).abs() < 1e-12);
+    assert!((south - (60.0 - step / 2.0)).abs() < 1e-12);
+
+    let (column, row) = georeference.transform.model_to_pixel(30.5, 60.5).unwrap();
+    assert!((column - 1800.0).abs() < 1e-6);
+    assert!((row - 1800.0).abs() < 1e-6);
+  }
+
+  #[test]
+  fn test_model_transformation() {
+    #[rustfmt::skip]
+    let matrix = [
+      2.0, 0.5, 0.0, 100.0,
+      0.0, -2.0, 0.0, 50.0,
+      0.0, 0.0, 0.0, 0.0,
+      0.0, 0.0, 0.0, 1.0,
+    ];
+    let buf = TiffBuilder::new().doubles(TAG_MODEL_TRANSFORMATION, &matrix).build();
+    let tiff = TiffFile::from_bytes(&buf).unwrap();
+    let georeference = Georeference::from_ifd(&tiff.ifds[0]).unwrap().unwrap();
+
+    assert_eq!(georeference.transform, GeoTransform([100.0, 2.0, 0.5, 50.0, 0.0, -2.0]));
+    assert_eq!(georeference.raster_type, RasterType::PixelIsArea);
+    assert_eq!(georeference.transform.pixel_to_model(3.0, 4.0), (108.0, 42.0));
+    assert_eq!(georeference.transform.model_to_pixel(108.0, 42.0), Some((3.0, 4.0)));
+  }
+
+  #[test]
+  fn test_not_georeferenced() {
+    let buf = TiffBuilder::new().doubles(TAG_MODEL_PIXEL_SCALE, &[1.0, 1.0, 0.0]).build();
+    let tiff = TiffFile::from_bytes(&buf).unwrap();
+
+    assert_eq!(Georeference::from_ifd(&tiff.ifds[0]).unwrap(), None);
+  }
+}
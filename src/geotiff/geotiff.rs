@@ -1,26 +1,334 @@
 use std::path::Path;
 
-use super::{TiffFile, TiffParserError};
+use super::{parser::Ifd, GeoTransform, Georeference, TiffFile, TiffParserError};
+
+pub(super) const TAG_GDAL_NODATA: u16 = 42113;
 
 #[derive(Debug)]
 pub struct GeoTiff {
-  tiff: TiffFile,
+  /// Full resolution image followed by its overviews, finest first.
+  levels: Vec<Ifd>,
+  georeference: Option<Georeference>,
+  nodata: Option<f64>,
 }
 
 impl GeoTiff {
+  /// Maps the file into memory, see [`TiffFile::from_file`].
   pub fn from_file<P: AsRef<Path>>(name: P) -> Result<Self, TiffParserError> {
     let tiff = TiffFile::from_file(name)?;
 
-    Ok(Self { tiff })
+    Self::from_tiff(tiff)
+  }
+
+  pub fn from_bytes(buf: &[u8]) -> Result<Self, TiffParserError> {
+    let tiff = TiffFile::from_bytes(buf)?;
+
+    Self::from_tiff(tiff)
+  }
+
+  /// Takes the images of an already parsed file, the first one being the full
+  /// resolution raster.
+  pub fn from_tiff(tiff: TiffFile) -> Result<Self, TiffParserError> {
+    let mut levels = tiff.into_pyramid();
+    let (georeference, nodata) = match levels.first() {
+      Some(ifd) => {
+        let nodata = match ifd.get_value(TAG_GDAL_NODATA) {
+          Ok(value) => Some(value.ascii()?.trim().parse::<f64>().map_err(|_| {
+            TiffParserError::InvalidValue(value.clone(), "expected nodata value")
+          })?),
+          Err(_) => None,
+        };
+        (Georeference::from_ifd(ifd)?, nodata)
+      }
+      None => (None, None),
+    };
+    for data in levels.iter_mut().filter_map(|ifd| ifd.data.as_mut()) {
+      data.set_nodata(nodata);
+    }
+
+    Ok(Self {
+      levels,
+      georeference,
+      nodata,
+    })
+  }
+
+  pub fn georeference(&self) -> Option<&Georeference> {
+    self.georeference.as_ref()
+  }
+
+  /// Value marking voids in the raster, from the GDAL_NODATA tag.
+  pub fn nodata(&self) -> Option<f64> {
+    self.nodata
+  }
+
+  /// Width and length of the full resolution raster in pixels.
+  pub fn size(&self) -> Option<(usize, usize)> {
+    self.level_size(0)
+  }
+
+  /// Number of resolution levels, the full resolution raster included.
+  pub fn levels(&self) -> usize {
+    self.levels.len()
   }
 
-  pub fn get_pixel(&self, lon: usize, lat: usize) -> i32 {
-    let ifd = &self.tiff.ifds[0];
-    let width = ifd.image_width().unwrap() as usize;
-    let length = ifd.image_length().unwrap() as usize;
-    ifd.data
-      .get((length - 1 - lat) * width + lon)
-      .cloned()
+  /// Width and length of the raster at `level` in pixels, 0 being full resolution.
+  pub fn level_size(&self, level: usize) -> Option<(usize, usize)> {
+    let data = self.levels.get(level)?.data.as_ref()?;
+    Some((data.width(), data.length()))
+  }
+
+  /// Transform from the outer corners of the pixels at `level` to model
+  /// coordinates. Overviews cover the extent of the full resolution raster with
+  /// proportionally larger pixels.
+  pub fn level_transform(&self, level: usize) -> Option<GeoTransform> {
+    let georeference = self.georeference.as_ref()?;
+    let (width, length) = self.size()?;
+    let (level_width, level_length) = self.level_size(level)?;
+    Some(georeference.corner_transform().scaled(
+      width as f64 / level_width as f64,
+      length as f64 / level_length as f64,
+    ))
+  }
+
+  /// Coarsest level whose pixels span at most `decimation` full resolution
+  /// pixels, falling back to full resolution.
+  pub fn level_for_decimation(&self, decimation: f64) -> usize {
+    let (width, _) = match self.size() {
+      Some(size) => size,
+      None => return 0,
+    };
+    (1..self.levels.len())
+      .take_while(|level| {
+        self.level_size(*level)
+          .is_some_and(|(level_width, _)| width as f64 / level_width as f64 <= decimation)
+      })
+      .last()
       .unwrap_or(0)
   }
-}
\ No newline at end of file
+
+  /// Decompresses every strip or tile of the raster at `level` up front on up
+  /// to `threads` threads, instead of on first access.
+  pub fn decode_level(&self, level: usize, threads: usize) -> Result<(), TiffParserError> {
+    match self.levels.get(level).and_then(|ifd| ifd.data.as_ref()) {
+      Some(data) => data.decode_all(threads),
+      None => Ok(()),
+    }
+  }
+
+  /// Decompresses the strips or tiles of the raster at `level` holding the
+  /// first band of `pixels`, given as `(lon, lat)` like in
+  /// [`GeoTiff::get_band_pixel`], on up to `threads` threads. Already decoded
+  /// blocks and pixels outside the raster are skipped.
+  pub fn decode_pixels(
+    &self,
+    level: usize,
+    pixels: &[(usize, usize)],
+    threads: usize,
+  ) -> Result<(), TiffParserError> {
+    let data = match self.levels.get(level).and_then(|ifd| ifd.data.as_ref()) {
+      Some(data) => data,
+      None => return Ok(()),
+    };
+    let pixels: Vec<_> = pixels
+      .iter()
+      .filter(|(_, lat)| *lat < data.length())
+      .map(|&(lon, lat)| (lon, data.length() - 1 - lat))
+      .collect();
+    data.decode_pixels(0, &pixels, threads)
+  }
+
+  /// Lowest and highest value of the first band of the raster at `level`,
+  /// voids excluded. `None` when the level is missing or holds only voids.
+  /// Decodes the whole level on up to `threads` threads, each block keeping its
+  /// range as it is decoded.
+  pub fn level_range(
+    &self,
+    level: usize,
+    threads: usize,
+  ) -> Result<Option<(f64, f64)>, TiffParserError> {
+    match self.levels.get(level).and_then(|ifd| ifd.data.as_ref()) {
+      Some(data) => {
+        data.decode_all(threads)?;
+        data.range()
+      }
+      None => Ok(None),
+    }
+  }
+
+  /// Number of samples per pixel of the full resolution raster.
+  pub fn bands(&self) -> usize {
+    self.levels.first().and_then(|ifd| ifd.data.as_ref()).map_or(0, |data| data.bands())
+  }
+
+  /// Returns the first band of the pixel, see [`GeoTiff::get_band_pixel`].
+  pub fn get_pixel(
+    &self,
+    level: usize,
+    lon: usize,
+    lat: usize,
+  ) -> Result<Option<f64>, TiffParserError> {
+    self.get_band_pixel(level, 0, lon, lat)
+  }
+
+  /// Returns `None` if the pixel or band is outside the raster at `level` or is
+  /// a void. Only the block holding the pixel is decoded, on its first access.
+  pub fn get_band_pixel(
+    &self,
+    level: usize,
+    band: usize,
+    lon: usize,
+    lat: usize,
+  ) -> Result<Option<f64>, TiffParserError> {
+    let data = match self.levels.get(level).and_then(|ifd| ifd.data.as_ref()) {
+      Some(data) => data,
+      None => return Ok(None),
+    };
+    if lat >= data.length() {
+      return Ok(None);
+    }
+    let value = match data.get(band, lon, data.length() - 1 - lat)? {
+      Some(value) => value,
+      None => return Ok(None),
+    };
+    match self.nodata {
+      Some(nodata) if data.is_nodata(band, value, nodata) => Ok(None),
+      _ => Ok(Some(value)),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::geotiff::georeference::{
+    KEY_RASTER_TYPE, TAG_GEO_KEY_DIRECTORY, TAG_MODEL_PIXEL_SCALE, TAG_MODEL_TIEPOINT,
+  };
+  use crate::geotiff::parser::{tags::*, testing::TiffBuilder};
+
+  fn srtm_tiff(nodata: Option<&str>) -> GeoTiff {
+    let pixels: [i16; 6] = [-32768, 15, 20, 25, 30, -32768];
+    let mut builder = TiffBuilder::new()
+      .image(3, 2, 16, 2)
+      .strips(2, vec![pixels.iter().flat_map(|x| x.to_le_bytes()).collect()]);
+    if let Some(nodata) = nodata {
+      builder = builder.ascii(TAG_GDAL_NODATA, nodata);
+    }
+    GeoTiff::from_tiff(TiffFile::from_bytes(&builder.build()).unwrap()).unwrap()
+  }
+
+  #[test]
+  fn test_nodata() {
+    let tiff = srtm_tiff(Some("-32768"));
+
+    assert_eq!(tiff.nodata(), Some(-32768.0));
+    assert_eq!(tiff.get_pixel(0, 0, 1).unwrap(), None);
+    assert_eq!(tiff.get_pixel(0, 1, 1).unwrap(), Some(15.0));
+    assert_eq!(tiff.get_pixel(0, 2, 0).unwrap(), None);
+  }
+
+  #[test]
+  fn test_without_nodata() {
+    let tiff = srtm_tiff(None);
+
+    assert_eq!(tiff.nodata(), None);
+    assert_eq!(tiff.get_pixel(0, 0, 1).unwrap(), Some(-32768.0));
+  }
+
+  #[test]
+  fn test_level_range() {
+    assert_eq!(srtm_tiff(Some("-32768")).level_range(0, 2).unwrap(), Some((15.0, 30.0)));
+    assert_eq!(srtm_tiff(None).level_range(0, 2).unwrap(), Some((-32768.0, 30.0)));
+    assert_eq!(srtm_tiff(None).level_range(1, 2).unwrap(), None);
+    assert_eq!(srtm_tiff(Some("15")).level_range(0, 2).unwrap(), Some((-32768.0, 30.0)));
+  }
+
+  #[test]
+  fn test_out_of_range() {
+    let tiff = srtm_tiff(Some("-32768"));
+
+    assert_eq!(tiff.get_pixel(0, 3, 0).unwrap(), None);
+    assert_eq!(tiff.get_pixel(0, 0, 2).unwrap(), None);
+  }
+
+  #[test]
+  fn test_float32_nodata() {
+    let pixels: [f32; 2] = [-9999.9, 1.5];
+    let buf = TiffBuilder::new()
+      .image(2, 1, 32, 3)
+      .strips(1, vec![pixels.iter().flat_map(|x| x.to_le_bytes()).collect()])
+      .ascii(TAG_GDAL_NODATA, "-9999.9")
+      .build();
+    let tiff = GeoTiff::from_tiff(TiffFile::from_bytes(&buf).unwrap()).unwrap();
+
+    assert_eq!(tiff.get_pixel(0, 0, 0).unwrap(), None);
+    assert_eq!(tiff.get_pixel(0, 1, 0).unwrap(), Some(1.5));
+  }
+
+  #[test]
+  fn test_overviews() {
+    let level = |size: u16, value: i16| {
+      let pixels = vec![value; size as usize * size as usize];
+      TiffBuilder::new()
+        .image(size, size, 16, 2)
+        .strips(size as u32, vec![pixels.iter().flat_map(|x| x.to_le_bytes()).collect()])
+    };
+    let buf = level(4, 100).overview(level(1, 300)).overview(level(2, 200)).build();
+    let tiff = GeoTiff::from_tiff(TiffFile::from_bytes(&buf).unwrap()).unwrap();
+
+    assert_eq!(tiff.levels(), 3);
+    assert_eq!(tiff.level_size(1), Some((2, 2)));
+    assert_eq!(tiff.level_size(2), Some((1, 1)));
+    assert_eq!(tiff.level_for_decimation(0.0), 0);
+    assert_eq!(tiff.level_for_decimation(1.0), 0);
+    assert_eq!(tiff.level_for_decimation(3.0), 1);
+    assert_eq!(tiff.level_for_decimation(100.0), 2);
+    assert_eq!(tiff.get_pixel(1, 1, 1).unwrap(), Some(200.0));
+    assert_eq!(tiff.get_pixel(2, 0, 0).unwrap(), Some(300.0));
+    assert_eq!(tiff.get_pixel(2, 1, 0).unwrap(), None);
+  }
+
+  #[test]
+  fn test_planar_bands() {
+    let buf = TiffBuilder::new()
+      .image(2, 1, 8, 1)
+      .shorts(TAG_SAMPLES_PER_PIXEL, &[2])
+      .shorts(TAG_PLANAR_CONFIGURATION, &[2])
+      .ascii(TAG_GDAL_NODATA, "0")
+      .strips(1, vec![vec![1, 2], vec![0, 4]])
+      .build();
+    let tiff = GeoTiff::from_tiff(TiffFile::from_bytes(&buf).unwrap()).unwrap();
+
+    assert_eq!(tiff.bands(), 2);
+    assert_eq!(tiff.get_pixel(0, 1, 0).unwrap(), Some(2.0));
+    assert_eq!(tiff.get_band_pixel(0, 1, 0, 0).unwrap(), None);
+    assert_eq!(tiff.get_band_pixel(0, 1, 1, 0).unwrap(), Some(4.0));
+    assert_eq!(tiff.get_band_pixel(0, 2, 1, 0).unwrap(), None);
+  }
+
+  #[test]
+  fn test_level_transform() {
+    // SRTM-like pixel-is-point tile N60E030 with a 4 times smaller overview
+    let step = 1.0 / 8.0;
+    let level = |size: u16| {
+      TiffBuilder::new()
+        .image(size, size, 16, 2)
+        .strips(size as u32, vec![vec![0; size as usize * size as usize * 2]])
+    };
+    let buf = level(9)
+      .doubles(TAG_MODEL_PIXEL_SCALE, &[step, step, 0.0])
+      .doubles(TAG_MODEL_TIEPOINT, &[0.0, 0.0, 0.0, 30.0, 61.0, 0.0])
+      .shorts(TAG_GEO_KEY_DIRECTORY, &[1, 1, 0, 1, KEY_RASTER_TYPE, 0, 1, 2])
+      .overview(level(3))
+      .build();
+    let tiff = GeoTiff::from_tiff(TiffFile::from_bytes(&buf).unwrap()).unwrap();
+
+    let transform = tiff.level_transform(0).unwrap();
+    assert_eq!(transform.pixel_to_model(0.5, 0.5), (30.0, 61.0));
+    assert_eq!(transform.model_to_pixel(31.0, 60.0), Some((8.5, 8.5)));
+    let transform = tiff.level_transform(1).unwrap();
+    assert_eq!(transform.pixel_to_model(0.0, 0.0), (30.0 - step / 2.0, 61.0 + step / 2.0));
+    assert_eq!(transform.pixel_to_model(3.0, 3.0), (31.0 + step / 2.0, 60.0 - step / 2.0));
+    assert_eq!(tiff.level_transform(2), None);
+  }
+}
@@ -1,5 +1,12 @@
 mod geotiff;
+mod georeference;
 mod parser;
+mod writer;
 
 pub use geotiff::GeoTiff;
-pub use parser::{TiffFile, TiffParserError};
\ No newline at end of file
+pub use georeference::{GeoTransform, Georeference, ModelType, RasterType};
+pub use parser::tags::*;
+pub use parser::{
+  compression_name, Compression, Ifd, IfdEntry, TiffFile, TiffParserError, Value,
+};
+pub use writer::{GeoTiffWriter, Samples};
@@ -0,0 +1,49 @@
+use super::TiffParserError;
+
+/// Returns `len` bytes of `buf` starting at `offset`, or an error if the file
+/// ends before them.
+pub(super) fn slice(buf: &[u8], offset: usize, len: usize) -> Result<&[u8], TiffParserError> {
+  offset
+    .checked_add(len)
+    .and_then(|end| buf.get(offset..end))
+    .ok_or(TiffParserError::Truncated(offset, len, buf.len()))
+}
+
+/// Returns the bytes of `buf` from `offset` on, or an error if `offset` is
+/// past the end of the file.
+pub(super) fn tail(buf: &[u8], offset: usize) -> Result<&[u8], TiffParserError> {
+  buf.get(offset..).ok_or(TiffParserError::Truncated(offset, 0, buf.len()))
+}
+
+/// Returns `len` zeros, or an error rather than an abort when they can't be
+/// allocated.
+pub(super) fn zeroed<T: Clone + Default>(len: usize) -> Result<Vec<T>, TiffParserError> {
+  let mut vec = Vec::new();
+  vec.try_reserve_exact(len)?;
+  vec.resize(len, T::default());
+  Ok(vec)
+}
+
+/// Pads `bytes` with zeros to `len`, reporting rather than aborting when they
+/// can't be allocated.
+pub(super) fn pad(mut bytes: Vec<u8>, len: usize) -> Result<Vec<u8>, TiffParserError> {
+  bytes.try_reserve_exact(len.saturating_sub(bytes.len()))?;
+  bytes.resize(len, 0);
+  Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_out_of_bounds() {
+    let buf = [1, 2, 3, 4];
+
+    assert_eq!(slice(&buf, 1, 3).unwrap(), &[2, 3, 4]);
+    assert_eq!(tail(&buf, 4).unwrap(), &[] as &[u8]);
+    assert!(matches!(slice(&buf, 2, 3), Err(TiffParserError::Truncated(2, 3, 4))));
+    assert!(matches!(slice(&buf, usize::MAX, 2), Err(TiffParserError::Truncated(_, 2, 4))));
+    assert!(matches!(tail(&buf, 5), Err(TiffParserError::Truncated(5, 0, 4))));
+  }
+}
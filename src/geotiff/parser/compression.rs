@@ -1,59 +1,339 @@
-use weezl::{decode::Decoder, BitOrder, LzwStatus};
+use std::{
+  borrow::Cow,
+  io::{Read, Write},
+};
 
-use super::TiffParserError;
+use flate2::{read::ZlibDecoder, write::ZlibEncoder};
+use weezl::{decode::Decoder, encode::Encoder, BitOrder, LzwStatus};
+
+use super::{
+  bounds::{pad, slice},
+  endianness::Endianness,
+  lerc, TiffParserError,
+};
 
 pub trait Decompressor {
   fn decompress(&mut self, bytes: &[u8], size: usize) -> Result<Vec<u8>, TiffParserError>;
+
+  /// Like `decompress`, also returning which pixels are valid for codecs that
+  /// store a mask of their own.
+  fn decompress_masked(
+    &mut self,
+    bytes: &[u8],
+    size: usize,
+  ) -> Result<(Vec<u8>, Option<Vec<bool>>), TiffParserError> {
+    Ok((self.decompress(bytes, size)?, None))
+  }
 }
 
 const COMPRESSION_NONE: u16 = 1;
 const COMPRESSION_LZW: u16 = 5;
+const COMPRESSION_ADOBE_DEFLATE: u16 = 8;
+const COMPRESSION_DEFLATE: u16 = 32946;
+const COMPRESSION_LERC: u16 = 34887;
+const COMPRESSION_ZSTD: u16 = 50000;
+
+/// Bytes LZW decodes at a time.
+const LZW_CHUNK_SIZE: usize = 1 << 16;
+
+/// Compression applied on top of LERC, the second value of the LercParameters tag.
+const LERC_ADD_COMPRESSION_NONE: u16 = 0;
+const LERC_ADD_COMPRESSION_DEFLATE: u16 = 1;
+const LERC_ADD_COMPRESSION_ZSTD: u16 = 2;
 
-pub fn create_decompressor(compression: u16) -> Result<Box<dyn Decompressor>, TiffParserError> {
+/// Name of a TIFF compression scheme, whether or not it can be decoded.
+pub fn compression_name(compression: u16) -> Option<&'static str> {
+  let name = match compression {
+    COMPRESSION_NONE => "None",
+    2 => "CCITT RLE",
+    COMPRESSION_LZW => "LZW",
+    7 => "JPEG",
+    COMPRESSION_ADOBE_DEFLATE => "Adobe Deflate",
+    32773 => "PackBits",
+    COMPRESSION_DEFLATE => "Deflate",
+    COMPRESSION_LERC => "LERC",
+    34925 => "LZMA",
+    COMPRESSION_ZSTD => "ZSTD",
+    50001 => "WebP",
+    _ => return None,
+  };
+  Some(name)
+}
+
+/// `endianness` and `lerc_compression` are only used by LERC, which decodes to
+/// typed samples rather than bytes.
+pub fn create_decompressor(
+  compression: u16,
+  endianness: Endianness,
+  lerc_compression: u16,
+) -> Result<Box<dyn Decompressor>, TiffParserError> {
   match compression {
     COMPRESSION_NONE => Ok(Box::new(DummyDecompressor)),
     COMPRESSION_LZW => Ok(Box::new(Decoder::with_tiff_size_switch(BitOrder::Msb, 8))),
+    COMPRESSION_ADOBE_DEFLATE | COMPRESSION_DEFLATE => Ok(Box::new(DeflateDecompressor)),
+    COMPRESSION_ZSTD => Ok(Box::new(ZstdDecompressor)),
+    COMPRESSION_LERC => match lerc_compression {
+      LERC_ADD_COMPRESSION_NONE | LERC_ADD_COMPRESSION_DEFLATE | LERC_ADD_COMPRESSION_ZSTD => {
+        Ok(Box::new(LercDecompressor { endianness, compression: lerc_compression }))
+      }
+      _ => Err(TiffParserError::UnknownCompression(lerc_compression)),
+    },
     compression => Err(TiffParserError::UnknownCompression(compression)),
   }
 }
 
+/// Compression schemes images can be written with.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Compression {
+  None,
+  Lzw,
+  Deflate,
+  Zstd,
+}
+
+impl Compression {
+  pub(crate) fn to_u16(self) -> u16 {
+    match self {
+      Compression::None => COMPRESSION_NONE,
+      Compression::Lzw => COMPRESSION_LZW,
+      Compression::Deflate => COMPRESSION_ADOBE_DEFLATE,
+      Compression::Zstd => COMPRESSION_ZSTD,
+    }
+  }
+
+  pub(crate) fn compress(self, bytes: &[u8]) -> Result<Vec<u8>, TiffParserError> {
+    match self {
+      Compression::None => Ok(bytes.to_vec()),
+      Compression::Lzw => Ok(Encoder::with_tiff_size_switch(BitOrder::Msb, 8).encode(bytes)?),
+      Compression::Deflate => {
+        let mut encoder = ZlibEncoder::new(vec![], flate2::Compression::default());
+        encoder.write_all(bytes).map_err(TiffParserError::Deflate)?;
+        encoder.finish().map_err(TiffParserError::Deflate)
+      }
+      Compression::Zstd => zstd::encode_all(bytes, 0).map_err(TiffParserError::Zstd),
+    }
+  }
+}
+
 struct DummyDecompressor;
 
 impl Decompressor for DummyDecompressor {
   fn decompress(&mut self, bytes: &[u8], size: usize) -> Result<Vec<u8>, TiffParserError> {
-    Ok(bytes[..size].to_vec())
+    Ok(slice(bytes, 0, size)?.to_vec())
+  }
+}
+
+struct DeflateDecompressor;
+
+impl Decompressor for DeflateDecompressor {
+  fn decompress(&mut self, bytes: &[u8], size: usize) -> Result<Vec<u8>, TiffParserError> {
+    // the output grows with the data rather than trusting the size of the block
+    let mut result = vec![];
+    ZlibDecoder::new(bytes)
+      .take(size as u64)
+      .read_to_end(&mut result)
+      .map_err(TiffParserError::Deflate)?;
+    // short tiles at the image edge may be stored without padding
+    pad(result, size)
+  }
+}
+
+struct ZstdDecompressor;
+
+impl Decompressor for ZstdDecompressor {
+  fn decompress(&mut self, bytes: &[u8], size: usize) -> Result<Vec<u8>, TiffParserError> {
+    let mut result = vec![];
+    zstd::Decoder::new(bytes)
+      .and_then(|decoder| decoder.take(size as u64).read_to_end(&mut result))
+      .map_err(TiffParserError::Zstd)?;
+    pad(result, size)
+  }
+}
+
+struct LercDecompressor {
+  endianness: Endianness,
+  compression: u16,
+}
+
+impl LercDecompressor {
+  /// Undoes the compression applied on top of the blob. A blob is at most a
+  /// little larger than the samples it holds, which bounds how much is inflated.
+  fn unwrap_blob<'a>(
+    &self,
+    bytes: &'a [u8],
+    size: usize,
+  ) -> Result<Cow<'a, [u8]>, TiffParserError> {
+    let limit = size.saturating_mul(2).saturating_add(4096) as u64;
+    let mut blob = vec![];
+    match self.compression {
+      LERC_ADD_COMPRESSION_DEFLATE => {
+        ZlibDecoder::new(bytes)
+          .take(limit)
+          .read_to_end(&mut blob)
+          .map_err(TiffParserError::Deflate)?;
+      }
+      LERC_ADD_COMPRESSION_ZSTD => {
+        zstd::Decoder::new(bytes)
+          .and_then(|decoder| decoder.take(limit).read_to_end(&mut blob))
+          .map_err(TiffParserError::Zstd)?;
+      }
+      _ => return Ok(Cow::Borrowed(bytes)),
+    }
+    Ok(Cow::Owned(blob))
+  }
+}
+
+impl Decompressor for LercDecompressor {
+  fn decompress(&mut self, bytes: &[u8], size: usize) -> Result<Vec<u8>, TiffParserError> {
+    Ok(self.decompress_masked(bytes, size)?.0)
+  }
+
+  fn decompress_masked(
+    &mut self,
+    bytes: &[u8],
+    size: usize,
+  ) -> Result<(Vec<u8>, Option<Vec<bool>>), TiffParserError> {
+    let blob = self.unwrap_blob(bytes, size)?;
+    let (samples, mask) = lerc::decode(&blob, self.endianness, size)?;
+    Ok((samples, Some(mask)))
   }
 }
 
 impl Decompressor for Decoder {
   fn decompress(&mut self, bytes: &[u8], size: usize) -> Result<Vec<u8>, TiffParserError> {
-    let mut result = vec![0; size];
+    // decoded a chunk at a time, so that the output only grows with the data
+    let mut result = vec![];
+    let mut chunk = [0; LZW_CHUNK_SIZE];
     let mut consumed_in = 0;
-    let mut consumed_out = 0;
-    loop {
-      let decode_result =
-        self.decode_bytes(&bytes[consumed_in..], &mut result[consumed_out..]);
+    while result.len() < size {
+      let len = chunk.len().min(size - result.len());
+      let decode_result = self.decode_bytes(&bytes[consumed_in..], &mut chunk[..len]);
       consumed_in += decode_result.consumed_in;
-      consumed_out += decode_result.consumed_out;
+      result.try_reserve(decode_result.consumed_out)?;
+      result.extend_from_slice(&chunk[..decode_result.consumed_out]);
       match decode_result.status {
         Ok(LzwStatus::Ok) => {}
-        Ok(LzwStatus::NoProgress) => {
-          return Ok(result);
-        }
-        Ok(LzwStatus::Done) => {
-          return Ok(result);
-        }
-        Err(err) => {
-          if consumed_out >= size {
-            return Ok(result);
-          } else {
-            return Err(err.into());
-          }
-        }
-      }
-      if consumed_out >= size {
-        return Ok(result);
+        Ok(LzwStatus::NoProgress) | Ok(LzwStatus::Done) => break,
+        Err(_) if result.len() >= size => break,
+        Err(err) => return Err(err.into()),
       }
     }
+    pad(result, size)
   }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // 4x4 little-endian i16 tile, compressed with zlib at level 9
+  const DEFLATE_TILE: [u8; 40] = [
+    0x78, 0xda, 0x7b, 0xf3, 0xff, 0xf3, 0xff, 0x5f, 0xff, 0x19, 0x19, 0x38, 0x18, 0xf8, 0x19, 0xc4,
+    0x18, 0x64, 0x19, 0x54, 0x18, 0xb4, 0x19, 0x8c, 0x18, 0x2c, 0x19, 0x1c, 0x18, 0xdc, 0x19, 0xfc,
+    0x18, 0x42, 0x19, 0x00, 0xc0, 0xb9, 0x08, 0x06,
+  ];
+
+  fn decompressor(compression: u16) -> Box<dyn Decompressor> {
+    create_decompressor(compression, Endianness::LittleEndian, LERC_ADD_COMPRESSION_NONE).unwrap()
+  }
+
+  fn tile_pixels() -> Vec<i16> {
+    (0..16).map(|x| x * 7 - 20).collect()
+  }
+
+  fn decode_i16(bytes: &[u8]) -> Vec<i16> {
+    bytes
+      .chunks(2)
+      .map(|b| i16::from_le_bytes([b[0], b[1]]))
+      .collect()
+  }
+
+  #[test]
+  fn test_adobe_deflate() {
+    let mut decompressor = decompressor(COMPRESSION_ADOBE_DEFLATE);
+    let bytes = decompressor.decompress(&DEFLATE_TILE, 32).unwrap();
+
+    assert_eq!(decode_i16(&bytes), tile_pixels());
+  }
+
+  #[test]
+  fn test_deflate() {
+    let mut decompressor = decompressor(COMPRESSION_DEFLATE);
+    let bytes = decompressor.decompress(&DEFLATE_TILE, 32).unwrap();
+
+    assert_eq!(decode_i16(&bytes), tile_pixels());
+  }
+
+  #[test]
+  fn test_deflate_corrupted() {
+    let mut decompressor = decompressor(COMPRESSION_DEFLATE);
+
+    assert!(decompressor.decompress(&DEFLATE_TILE[4..], 32).is_err());
+  }
+
+  #[test]
+  fn test_round_trip() {
+    let bytes: Vec<u8> = tile_pixels().iter().flat_map(|x| x.to_le_bytes()).collect();
+    let compressions = [
+      Compression::None,
+      Compression::Lzw,
+      Compression::Deflate,
+      Compression::Zstd,
+    ];
+    for compression in compressions {
+      let compressed = compression.compress(&bytes).unwrap();
+      let decompressed = decompressor(compression.to_u16())
+        .decompress(&compressed, bytes.len())
+        .unwrap();
+
+      assert_eq!(decompressed, bytes);
+    }
+  }
+
+  #[test]
+  fn test_grows_output() {
+    // more than a chunk of LZW output, padded when the stream is shorter than the block
+    let bytes: Vec<u8> = (0..LZW_CHUNK_SIZE * 2 + 100).map(|x| (x % 251) as u8).collect();
+    for compression in [Compression::Lzw, Compression::Deflate, Compression::Zstd] {
+      let compressed = compression.compress(&bytes).unwrap();
+      let decompressed = decompressor(compression.to_u16())
+        .decompress(&compressed, bytes.len())
+        .unwrap();
+      let padded = decompressor(compression.to_u16())
+        .decompress(&compressed, bytes.len() + 10)
+        .unwrap();
+
+      assert_eq!(decompressed, bytes);
+      assert_eq!(padded[..bytes.len()], bytes);
+      assert_eq!(padded[bytes.len()..], [0; 10]);
+    }
+  }
+
+  #[test]
+  fn test_wrapped_lerc() {
+    // 2x1 f32 LERC blob holding 8848.86 and -11034.0
+    let blob = [
+      0x4c, 0x65, 0x72, 0x63, 0x32, 0x20, 0x02, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x02,
+      0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x08, 0x00, 0x00, 0x00, 0x47, 0x00, 0x00, 0x00,
+      0x06, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+      0x00, 0x00, 0x8d, 0xc5, 0xc0, 0x00, 0x00, 0x00, 0x20, 0x6e, 0x48, 0xc1, 0x40, 0x00, 0x00,
+      0x00, 0x00, 0x01, 0x71, 0x43, 0x0a, 0x46, 0x00, 0x68, 0x2c, 0xc6,
+    ];
+    let wrapped = [
+      (LERC_ADD_COMPRESSION_NONE, blob.to_vec()),
+      (LERC_ADD_COMPRESSION_DEFLATE, Compression::Deflate.compress(&blob).unwrap()),
+      (LERC_ADD_COMPRESSION_ZSTD, Compression::Zstd.compress(&blob).unwrap()),
+    ];
+    for (lerc_compression, bytes) in wrapped {
+      let (bytes, mask) =
+        create_decompressor(COMPRESSION_LERC, Endianness::LittleEndian, lerc_compression)
+          .unwrap()
+          .decompress_masked(&bytes, 8)
+          .unwrap();
+
+      assert_eq!(bytes[..4], 8848.86f32.to_le_bytes());
+      assert_eq!(bytes[4..], (-11034.0f32).to_le_bytes());
+      assert_eq!(mask, Some(vec![true, true]));
+    }
+  }
+}
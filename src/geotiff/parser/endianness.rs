@@ -1,6 +1,6 @@
 use std::convert::TryFrom;
 
-use super::TiffParserError;
+use super::{bounds::slice, TiffParserError};
 
 #[derive(Debug, Clone, Copy)]
 pub(super) enum Endianness {
@@ -10,7 +10,7 @@ pub(super) enum Endianness {
 
 impl Endianness {
   pub(super) fn read_i16(&self, buf: &[u8]) -> Result<i16, TiffParserError> {
-    let bytes = <[u8; 2]>::try_from(&buf[0..2])?;
+    let bytes = <[u8; 2]>::try_from(slice(buf, 0, 2)?)?;
     let val = match self {
       Endianness::LittleEndian => i16::from_le_bytes(bytes),
       Endianness::BigEndian => i16::from_be_bytes(bytes),
@@ -19,7 +19,7 @@ impl Endianness {
   }
 
   pub(super) fn read_u16(&self, buf: &[u8]) -> Result<u16, TiffParserError> {
-    let bytes = <[u8; 2]>::try_from(&buf[0..2])?;
+    let bytes = <[u8; 2]>::try_from(slice(buf, 0, 2)?)?;
     let val = match self {
       Endianness::LittleEndian => u16::from_le_bytes(bytes),
       Endianness::BigEndian => u16::from_be_bytes(bytes),
@@ -28,7 +28,7 @@ impl Endianness {
   }
 
   pub(super) fn read_i32(&self, buf: &[u8]) -> Result<i32, TiffParserError> {
-    let bytes = <[u8; 4]>::try_from(&buf[0..4])?;
+    let bytes = <[u8; 4]>::try_from(slice(buf, 0, 4)?)?;
     let val = match self {
       Endianness::LittleEndian => i32::from_le_bytes(bytes),
       Endianness::BigEndian => i32::from_be_bytes(bytes),
@@ -37,7 +37,7 @@ impl Endianness {
   }
 
   pub(super) fn read_u32(&self, buf: &[u8]) -> Result<u32, TiffParserError> {
-    let bytes = <[u8; 4]>::try_from(&buf[0..4])?;
+    let bytes = <[u8; 4]>::try_from(slice(buf, 0, 4)?)?;
     let val = match self {
       Endianness::LittleEndian => u32::from_le_bytes(bytes),
       Endianness::BigEndian => u32::from_be_bytes(bytes),
@@ -45,8 +45,26 @@ impl Endianness {
     Ok(val)
   }
 
+  pub(super) fn read_i64(&self, buf: &[u8]) -> Result<i64, TiffParserError> {
+    let bytes = <[u8; 8]>::try_from(slice(buf, 0, 8)?)?;
+    let val = match self {
+      Endianness::LittleEndian => i64::from_le_bytes(bytes),
+      Endianness::BigEndian => i64::from_be_bytes(bytes),
+    };
+    Ok(val)
+  }
+
+  pub(super) fn read_u64(&self, buf: &[u8]) -> Result<u64, TiffParserError> {
+    let bytes = <[u8; 8]>::try_from(slice(buf, 0, 8)?)?;
+    let val = match self {
+      Endianness::LittleEndian => u64::from_le_bytes(bytes),
+      Endianness::BigEndian => u64::from_be_bytes(bytes),
+    };
+    Ok(val)
+  }
+
   pub(super) fn read_f32(&self, buf: &[u8]) -> Result<f32, TiffParserError> {
-    let bytes = <[u8; 4]>::try_from(&buf[0..4])?;
+    let bytes = <[u8; 4]>::try_from(slice(buf, 0, 4)?)?;
     let val = match self {
       Endianness::LittleEndian => f32::from_le_bytes(bytes),
       Endianness::BigEndian => f32::from_be_bytes(bytes),
@@ -55,11 +73,27 @@ impl Endianness {
   }
 
   pub(super) fn read_f64(&self, buf: &[u8]) -> Result<f64, TiffParserError> {
-    let bytes = <[u8; 8]>::try_from(&buf[0..8])?;
+    let bytes = <[u8; 8]>::try_from(slice(buf, 0, 8)?)?;
     let val = match self {
       Endianness::LittleEndian => f64::from_le_bytes(bytes),
       Endianness::BigEndian => f64::from_be_bytes(bytes),
     };
     Ok(val)
   }
+
+  pub(super) fn write_u16(&self, val: u16, buf: &mut [u8]) {
+    let bytes = match self {
+      Endianness::LittleEndian => val.to_le_bytes(),
+      Endianness::BigEndian => val.to_be_bytes(),
+    };
+    buf[0..2].copy_from_slice(&bytes);
+  }
+
+  pub(super) fn write_u32(&self, val: u32, buf: &mut [u8]) {
+    let bytes = match self {
+      Endianness::LittleEndian => val.to_le_bytes(),
+      Endianness::BigEndian => val.to_be_bytes(),
+    };
+    buf[0..4].copy_from_slice(&bytes);
+  }
 }
\ No newline at end of file
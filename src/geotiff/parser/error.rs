@@ -1,4 +1,7 @@
-use std::{array::TryFromSliceError, io::Error as IoError, string::FromUtf8Error};
+use std::{
+  array::TryFromSliceError, collections::TryReserveError, io::Error as IoError,
+  string::FromUtf8Error,
+};
 
 use thiserror::Error;
 use weezl::LzwError;
@@ -17,8 +20,16 @@ pub enum TiffParserError {
   StringNotNullTerminated(Vec<u8>),
   #[error("Unknown field type: {0}")]
   UnknownFieldType(u16),
+  #[error("Truncated file: {1} bytes at offset {0} are past its end at {2}")]
+  Truncated(usize, usize, usize),
+  #[error("IFD at offset {0} is referenced more than once")]
+  IfdLoop(usize),
   #[error("Unknown endianness marker: {0:?}")]
   UnknownEndiannessMarker(Vec<u8>),
+  #[error("Unknown TIFF magic number: {0}")]
+  UnknownMagic(u16),
+  #[error("Unsupported BigTIFF offset size: {0}")]
+  UnsupportedOffsetSize(u16),
   #[error("The image has both tile and strip data")]
   ImageBothTiledAndStripped,
   #[error("Invalid value {0:?}, {1}")]
@@ -27,6 +38,32 @@ pub enum TiffParserError {
   MissingValue(u16),
   #[error("Unknown compression scheme: {0}")]
   UnknownCompression(u16),
+  #[error("Unknown sample format: {0}")]
+  UnknownSampleFormat(u16),
+  #[error("Unknown predictor: {0}")]
+  UnknownPredictor(u16),
+  #[error("Unsupported sample size: {0} bits per pixel")]
+  UnsupportedSampleSize(usize),
+  #[error("Expected {0} strips or tiles, found {1}")]
+  BlockCountMismatch(usize, usize),
+  #[error("A {0}x{1} block is too large to decode")]
+  BlockTooLarge(usize, usize),
+  #[error("Out of memory decoding a block: {0}")]
+  OutOfMemory(#[from] TryReserveError),
+  #[error("Expected {0} samples, got {1}")]
+  SampleCountMismatch(usize, usize),
+  #[error("Image of {0} bytes is too large for a classic TIFF")]
+  TooLarge(usize),
+  #[error("Image dimension of {0} pixels does not fit in a TIFF field")]
+  DimensionTooLarge(usize),
   #[error("LZW decompression error: {0}")]
   Lzw(#[from] LzwError),
+  #[error("Deflate decompression error: {0}")]
+  Deflate(IoError),
+  #[error("ZSTD decompression error: {0}")]
+  Zstd(IoError),
+  #[error("Invalid LERC blob: {0}")]
+  InvalidLerc(&'static str),
+  #[error("Unsupported LERC blob: {0}")]
+  UnsupportedLerc(&'static str),
 }
\ No newline at end of file
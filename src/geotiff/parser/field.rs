@@ -14,6 +14,9 @@ pub(crate) enum Field {
   Srational = 10,
   Float = 11,
   Double = 12,
+  Long8 = 16,
+  Slong8 = 17,
+  Ifd8 = 18,
 }
 
 impl Field {
@@ -31,6 +34,9 @@ impl Field {
       10 => Ok(Field::Srational),
       11 => Ok(Field::Float),
       12 => Ok(Field::Double),
+      16 => Ok(Field::Long8),
+      17 => Ok(Field::Slong8),
+      18 => Ok(Field::Ifd8),
       field => Err(TiffParserError::UnknownFieldType(field)),
     }
   }
@@ -41,6 +47,7 @@ impl Field {
       Field::Short | Field::Sshort => 2,
       Field::Long | Field::Slong | Field::Float => 4,
       Field::Rational | Field::Srational | Field::Double => 8,
+      Field::Long8 | Field::Slong8 | Field::Ifd8 => 8,
     }
   }
 }
\ No newline at end of file
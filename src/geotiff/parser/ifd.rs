@@ -1,7 +1,16 @@
-use std::fmt;
+use std::{collections::HashSet, fmt, sync::Arc};
 
 use super::{
-  compression::create_decompressor, endianness::Endianness, field::Field, tags::*, value::Value,
+  bounds::{slice, tail},
+  endianness::Endianness,
+  field::Field,
+  lazy_raster::{BlockLayout, LazyRaster},
+  predictor::Predictor,
+  raster::SampleFormat,
+  source::Source,
+  tags::*,
+  value::Value,
+  variant::TiffVariant,
   TiffParserError,
 };
 
@@ -12,16 +21,33 @@ pub struct IfdEntry {
 }
 
 impl IfdEntry {
-  fn read(endianness: Endianness, buf: &[u8], start: usize) -> Result<Self, TiffParserError> {
-    let tag = endianness.read_u16(&buf[start..])?;
-    let field = Field::from_u16(endianness.read_u16(&buf[start + 2..])?)?;
-    let count = endianness.read_u32(&buf[start + 4..])? as usize;
-    let num_bytes = count * field.size();
-    let bytes = if num_bytes <= 4 {
-      &buf[start + 8..start + 8 + num_bytes]
+  pub fn tag(&self) -> u16 {
+    self.tag
+  }
+
+  pub fn value(&self) -> &Value {
+    &self.value
+  }
+
+  fn read(
+    endianness: Endianness,
+    variant: TiffVariant,
+    buf: &[u8],
+    start: usize,
+  ) -> Result<Self, TiffParserError> {
+    let offset_size = variant.offset_size();
+    let entry = slice(buf, start, variant.entry_size())?;
+    let tag = endianness.read_u16(entry)?;
+    let field = Field::from_u16(endianness.read_u16(&entry[2..])?)?;
+    let count = variant.read_offset(endianness, &entry[4..])?;
+    let num_bytes = count
+      .checked_mul(field.size())
+      .ok_or(TiffParserError::Truncated(start, usize::MAX, buf.len()))?;
+    let bytes = if num_bytes <= offset_size {
+      &entry[4 + offset_size..4 + offset_size + num_bytes]
     } else {
-      let offset = endianness.read_u32(&buf[start + 8..])? as usize;
-      &buf[offset..offset + num_bytes]
+      let offset = variant.read_offset(endianness, &entry[4 + offset_size..])?;
+      slice(buf, offset, num_bytes)?
     };
     let value = Value::from_bytes(endianness, field, bytes)?;
     Ok(IfdEntry { tag, value })
@@ -31,27 +57,55 @@ impl IfdEntry {
 pub struct Ifd {
   pub entries: Vec<IfdEntry>,
   pub(super) sub_ifds: Vec<Ifd>,
-  pub(crate) data: Vec<i32>,
+  pub(crate) data: Option<LazyRaster>,
 }
 
+/// SubIFDs nested deeper than this are kept as plain entries instead of being read.
+const MAX_SUB_IFD_DEPTH: usize = 2;
+
 impl Ifd {
+  /// Reads the IFD at `start` and returns it with the offset of the next one.
+  /// Offsets already in `visited` are rejected, so that malformed files can't
+  /// loop forever.
   pub(super) fn read(
     endianness: Endianness,
-    buf: &[u8],
+    variant: TiffVariant,
+    source: &Arc<Source>,
     start: usize,
+    visited: &mut HashSet<usize>,
+    depth: usize,
   ) -> Result<(Self, usize), TiffParserError> {
-    let num_entries = endianness.read_u16(&buf[start..])? as usize;
+    if !visited.insert(start) {
+      return Err(TiffParserError::IfdLoop(start));
+    }
+    let buf: &[u8] = source;
+    let num_entries = variant.read_entry_count(endianness, tail(buf, start)?)?;
+    let entries_start = start + variant.entry_count_size();
+    let entries_size = num_entries
+      .checked_mul(variant.entry_size())
+      .ok_or(TiffParserError::Truncated(entries_start, usize::MAX, buf.len()))?;
+    // the entries and the next IFD offset must all be in the file
+    slice(buf, entries_start, entries_size)?;
+    let next_ifd_offset = variant.read_offset(
+      endianness,
+      slice(buf, entries_start + entries_size, variant.offset_size())?,
+    )?;
+
     let mut entries = vec![];
     let mut sub_ifds = vec![];
     for i in 0..num_entries {
-      let entry = IfdEntry::read(endianness, buf, start + 2 + i * 12)?;
-      match (&entry.tag, &entry.value) {
-        (&TAG_SUB_IFDS, &Value::Longs(ref longs)) => {
-          let mut offset = longs[0] as usize;
-          while offset != 0 {
-            let (sub_ifd, next_offset) = Ifd::read(endianness, buf, offset)?;
-            sub_ifds.push(sub_ifd);
-            offset = next_offset;
+      let entry_start = entries_start + i * variant.entry_size();
+      let entry = IfdEntry::read(endianness, variant, buf, entry_start)?;
+      match entry.tag {
+        TAG_SUB_IFDS if depth < MAX_SUB_IFD_DEPTH => {
+          for offset in entry.value.uints()? {
+            let mut offset = offset as usize;
+            while offset != 0 {
+              let (sub_ifd, next_offset) =
+                Ifd::read(endianness, variant, source, offset, visited, depth + 1)?;
+              sub_ifds.push(sub_ifd);
+              offset = next_offset;
+            }
           }
         }
         _ => {
@@ -59,103 +113,152 @@ impl Ifd {
         }
       }
     }
-    let next_ifd_offset = endianness.read_u32(&buf[start + 2 + num_entries * 12..])? as usize;
 
     let mut ifd = Ifd {
       entries,
       sub_ifds,
-      data: vec![],
+      data: None,
     };
-
-    ifd.read_data(endianness, buf)?;
+    ifd.data = ifd
+      .block_layout()?
+      .map(|layout| LazyRaster::new(layout, endianness, source.clone()))
+      .transpose()?;
 
     Ok((ifd, next_ifd_offset))
   }
 
-  pub fn image_width(&self) -> Option<u16> {
-    self.get_value(TAG_IMAGE_WIDTH).ok()?.short().ok()
-  }
-
-  pub fn image_length(&self) -> Option<u16> {
-    self.get_value(TAG_IMAGE_LENGTH).ok()?.short().ok()
-  }
-
-  fn read_data(&mut self, endianness: Endianness, buf: &[u8]) -> Result<(), TiffParserError> {
+  fn block_layout(&self) -> Result<Option<BlockLayout>, TiffParserError> {
     if self.is_stripped() && !self.is_tiled() {
-      self.read_data_stripped(endianness, buf)
+      self.stripped_layout().map(Some)
     } else if self.is_tiled() && !self.is_stripped() {
-      self.read_data_tiled(endianness, buf)
+      self.tiled_layout().map(Some)
     } else if self.is_tiled() && self.is_stripped() {
       Err(TiffParserError::ImageBothTiledAndStripped)
     } else {
       // no image data
-      Ok(())
+      Ok(None)
     }
   }
 
-  fn read_data_stripped(
-    &mut self,
-    _endianness: Endianness,
-    _buf: &[u8],
-  ) -> Result<(), TiffParserError> {
-    unimplemented!()
+  fn stripped_layout(&self) -> Result<BlockLayout, TiffParserError> {
+    let image_width = self.dimension(TAG_IMAGE_WIDTH)?;
+    let image_length = self.dimension(TAG_IMAGE_LENGTH)?;
+
+    // RowsPerStrip defaults to 2**32 - 1, i.e. the whole image is a single strip
+    let rows_per_strip = match self.get_value(TAG_ROWS_PER_STRIP)? {
+      value if value.uint()? == 0 => {
+        let value = value.clone();
+        return Err(TiffParserError::InvalidValue(value, "expected non-zero rows per strip"));
+      }
+      value => (value.uint()? as usize).min(image_length),
+    };
+
+    self.layout(
+      (image_width, image_length),
+      (image_width, rows_per_strip),
+      false,
+      (TAG_STRIP_OFFSETS, TAG_STRIP_BYTE_COUNTS),
+    )
   }
 
-  fn read_data_tiled(
-    &mut self,
-    endianness: Endianness,
-    buf: &[u8],
-  ) -> Result<(), TiffParserError> {
-    let tile_width = self.get_value(TAG_TILE_WIDTH)?.short()? as usize;
-    let tile_length = self.get_value(TAG_TILE_LENGTH)?.short()? as usize;
-    let tile_offsets = self.get_value(TAG_TILE_OFFSETS)?.longs()?;
-    let tile_byte_counts = self.get_value(TAG_TILE_BYTE_COUNTS)?.longs()?;
+  fn tiled_layout(&self) -> Result<BlockLayout, TiffParserError> {
+    self.layout(
+      (self.dimension(TAG_IMAGE_WIDTH)?, self.dimension(TAG_IMAGE_LENGTH)?),
+      (self.dimension(TAG_TILE_WIDTH)?, self.dimension(TAG_TILE_LENGTH)?),
+      true,
+      (TAG_TILE_OFFSETS, TAG_TILE_BYTE_COUNTS),
+    )
+  }
 
-    let image_width = self.get_value(TAG_IMAGE_WIDTH)?.short()? as usize;
-    let image_length = self.get_value(TAG_IMAGE_LENGTH)?.short()? as usize;
+  fn layout(
+    &self,
+    (image_width, image_length): (usize, usize),
+    (block_width, block_length): (usize, usize),
+    padded: bool,
+    (offsets_tag, byte_counts_tag): (u16, u16),
+  ) -> Result<BlockLayout, TiffParserError> {
+    let samples = self.samples_per_pixel()?;
+    let bits_per_sample = self.per_sample(TAG_BITS_PER_SAMPLE, samples)?;
+    let sample_formats = match self.has_entry(TAG_SAMPLE_FORMAT) {
+      true => self.per_sample(TAG_SAMPLE_FORMAT, samples)?,
+      false => vec![SampleFormat::Uint as u64; samples],
+    };
 
-    let bits_per_sample = self.get_value(TAG_BITS_PER_SAMPLE)?.short()? as usize;
-    let samples_per_pixel = self.get_value(TAG_SAMPLES_PER_PIXEL)?.short()? as usize;
-    let bytes_per_pixel = bits_per_sample * samples_per_pixel / 8;
+    Ok(BlockLayout {
+      image_width,
+      image_length,
+      block_width,
+      block_length,
+      padded,
+      bits_per_sample: bits_per_sample.iter().map(|bits| *bits as usize).collect(),
+      sample_formats: sample_formats
+        .iter()
+        .map(|format| SampleFormat::from_u16(*format as u16))
+        .collect::<Result<Vec<_>, TiffParserError>>()?,
+      planar: self.is_planar()?,
+      compression: self.get_value(TAG_COMPRESSION)?.short()?,
+      lerc_compression: self.lerc_compression()?,
+      predictor: self.predictor()?,
+      offsets: self.get_value(offsets_tag)?.uints()?,
+      byte_counts: self.get_value(byte_counts_tag)?.uints()?,
+    })
+  }
 
-    let compression = self.get_value(TAG_COMPRESSION)?.short()?;
+  /// Reads a size stored as SHORT or LONG, which blocks are divided by and so
+  /// must not be zero.
+  fn dimension(&self, tag: u16) -> Result<usize, TiffParserError> {
+    let value = self.get_value(tag)?;
+    match value.uint()? {
+      0 => Err(TiffParserError::InvalidValue(value.clone(), "expected non-zero size")),
+      size => Ok(size as usize),
+    }
+  }
 
-    let tiles = tile_offsets
-      .iter()
-      .zip(tile_byte_counts.iter())
-      .map(|(offset, count)| {
-        let offset = *offset as usize;
-        let count = *count as usize;
-        &buf[offset..offset + count]
-      })
-      .map(|enc_tile| {
-        create_decompressor(compression).and_then(|mut decompressor| {
-          decompressor
-            .decompress(enc_tile, tile_width * tile_length * bits_per_sample / 8)
-        })
-      })
-      .collect::<Result<Vec<_>, _>>()?;
-
-    self.data = Vec::with_capacity(image_length * image_width);
-    let nrow = (image_width + tile_width - 1) / tile_width;
-    for j in 0..image_length {
-      let tile_row = j / tile_length;
-      let jt = j - tile_row * tile_length;
-      for i in 0..image_width {
-        let tile_col = i / tile_width;
-        let it = i - tile_col * tile_width;
-        let bytes =
-          &tiles[tile_row * nrow + tile_col][bytes_per_pixel * (jt * tile_width + it)..];
-        let pixel = match bytes_per_pixel {
-          2 => endianness.read_i16(bytes)? as i32,
-          4 => endianness.read_i32(bytes)?,
-          _ => unimplemented!(),
-        };
-        self.data.push(pixel);
+  fn samples_per_pixel(&self) -> Result<usize, TiffParserError> {
+    match self.get_value(TAG_SAMPLES_PER_PIXEL) {
+      Ok(value) if value.short()? == 0 => {
+        Err(TiffParserError::InvalidValue(value.clone(), "expected at least one sample"))
       }
+      Ok(value) => Ok(value.short()? as usize),
+      Err(_) => Ok(1),
     }
+  }
 
-    Ok(())
+  /// Reads a tag with one value per sample, or a single value shared by all samples.
+  fn per_sample(&self, tag: u16, samples: usize) -> Result<Vec<u64>, TiffParserError> {
+    let value = self.get_value(tag)?;
+    let vals = value.uints()?;
+    match vals.len() {
+      1 => Ok(vec![vals[0]; samples]),
+      len if len == samples => Ok(vals),
+      _ => Err(TiffParserError::InvalidValue(value.clone(), "expected one value per sample")),
+    }
+  }
+
+  /// Compression applied on top of LERC, the second LercParameters value.
+  fn lerc_compression(&self) -> Result<u16, TiffParserError> {
+    match self.get_value(TAG_LERC_PARAMETERS) {
+      Ok(value) => Ok(value.uints()?.get(1).map_or(0, |c| (*c).min(u16::MAX as u64) as u16)),
+      Err(_) => Ok(0),
+    }
+  }
+
+  fn is_planar(&self) -> Result<bool, TiffParserError> {
+    match self.get_value(TAG_PLANAR_CONFIGURATION) {
+      Ok(value) => match value.short()? {
+        1 => Ok(false),
+        2 => Ok(true),
+        _ => Err(TiffParserError::InvalidValue(value.clone(), "expected planar configuration")),
+      },
+      Err(_) => Ok(false),
+    }
+  }
+
+  fn predictor(&self) -> Result<Predictor, TiffParserError> {
+    match self.get_value(TAG_PREDICTOR) {
+      Ok(value) => Predictor::from_u16(value.short()?),
+      Err(_) => Ok(Predictor::None),
+    }
   }
 
   fn is_stripped(&self) -> bool {
@@ -171,6 +274,29 @@ impl Ifd {
       && self.has_entry(TAG_TILE_BYTE_COUNTS)
   }
 
+  /// Whether NewSubfileType marks this image as a reduced-resolution version
+  /// of another image in the file.
+  pub(super) fn is_reduced_resolution(&self) -> bool {
+    self.subfile_type() & 1 != 0
+  }
+
+  /// Whether NewSubfileType marks this image as a transparency mask.
+  pub(super) fn is_mask(&self) -> bool {
+    self.subfile_type() & 4 != 0
+  }
+
+  fn subfile_type(&self) -> u64 {
+    match self.get_value(TAG_NEW_SUBFILE_TYPE) {
+      Ok(value) => value.uint().unwrap_or(0),
+      Err(_) => 0,
+    }
+  }
+
+  /// IFDs referenced from the SubIFDs tag, such as overviews and masks.
+  pub fn sub_ifds(&self) -> &[Ifd] {
+    &self.sub_ifds
+  }
+
   pub fn has_entry(&self, tag: u16) -> bool {
     self.entries.iter().any(|entry| entry.tag == tag)
   }
@@ -211,7 +337,147 @@ impl fmt::Debug for Ifd {
     f.debug_struct("Ifd")
       .field("entries", &self.entries)
       .field("sub_ifds", &self.sub_ifds)
-      .field("data", &format!("({} pixels)", self.data.len()))
+      .field("data", &self.data)
       .finish()
   }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::geotiff::parser::{testing::TiffBuilder, Compression};
+
+  fn read(buf: Vec<u8>) -> Result<(Ifd, usize), TiffParserError> {
+    let source = Arc::new(Source::Memory(buf));
+    Ifd::read(Endianness::LittleEndian, TiffVariant::Classic, &source, 8, &mut HashSet::new(), 0)
+  }
+
+  fn samples(ifd: &Ifd) -> Vec<f64> {
+    let data = ifd.data.as_ref().unwrap();
+    (0..data.length())
+      .flat_map(|y| (0..data.width()).map(move |x| (x, y)))
+      .map(|(x, y)| data.get(0, x, y).unwrap().unwrap())
+      .collect()
+  }
+
+  #[test]
+  fn test_read_stripped() {
+    let pixels: Vec<i16> = (0..15).map(|x| x * 10 - 50).collect();
+    let bytes: Vec<u8> = pixels.iter().flat_map(|x| x.to_le_bytes()).collect();
+    let buf = TiffBuilder::new()
+      .image(3, 5, 16, 2)
+      .strips(2, bytes.chunks(12).map(<[u8]>::to_vec).collect())
+      .build();
+    let (ifd, next) = read(buf).unwrap();
+
+    assert_eq!(next, 0);
+    assert_eq!(ifd.data.as_ref().unwrap().decoded_blocks(), 0);
+    assert_eq!(samples(&ifd), pixels.iter().map(|x| *x as f64).collect::<Vec<_>>());
+    assert_eq!(ifd.data.as_ref().unwrap().decoded_blocks(), 3);
+  }
+
+  #[test]
+  fn test_read_stripped_single_strip() {
+    let pixels: Vec<u16> = (0..12).map(|x| x * 5000).collect();
+    let bytes: Vec<u8> = pixels.iter().flat_map(|x| x.to_le_bytes()).collect();
+    let buf = TiffBuilder::new()
+      .image(4, 3, 16, 1)
+      .strips(u16::MAX as u32, vec![bytes])
+      .build();
+    let (ifd, _) = read(buf).unwrap();
+
+    assert_eq!(samples(&ifd), pixels.iter().map(|x| *x as f64).collect::<Vec<_>>());
+  }
+
+  #[test]
+  fn test_read_float32() {
+    let pixels: Vec<f32> = vec![-12.75, 0.125, 1234.5, 8848.86, -0.001, 3.0];
+    let bytes: Vec<u8> = pixels.iter().flat_map(|x| x.to_le_bytes()).collect();
+    let buf = TiffBuilder::new()
+      .image(3, 2, 32, 3)
+      .strips(1, bytes.chunks(12).map(<[u8]>::to_vec).collect())
+      .build();
+    let (ifd, _) = read(buf).unwrap();
+
+    assert_eq!(ifd.data.as_ref().unwrap().get(0, 0, 1).unwrap(), Some(8848.86f32 as f64));
+    assert_eq!(samples(&ifd), pixels.iter().map(|x| *x as f64).collect::<Vec<_>>());
+  }
+
+  #[test]
+  fn test_read_unsupported_sample_size() {
+    let buf = TiffBuilder::new()
+      .image(2, 1, 64, 2)
+      .strips(1, vec![vec![0; 16]])
+      .build();
+
+    assert!(matches!(
+      read(buf),
+      Err(TiffParserError::UnsupportedSampleSize(64))
+    ));
+  }
+
+  #[test]
+  fn test_read_long_dimensions() {
+    let buf = TiffBuilder::new()
+      .image(0, 0, 8, 1)
+      .longs(TAG_IMAGE_WIDTH, &[3])
+      .longs(TAG_IMAGE_LENGTH, &[1])
+      .strips(1, vec![vec![7, 8, 9]])
+      .build();
+    let (ifd, _) = read(buf).unwrap();
+
+    assert_eq!(samples(&ifd), vec![7.0, 8.0, 9.0]);
+  }
+
+  #[test]
+  fn test_read_mixed_samples() {
+    // a Float32 elevation interleaved with an 8-bit quality band
+    let pixels: Vec<u8> = [(12.5f32, 1u8), (-3.25, 2)]
+      .iter()
+      .flat_map(|(elevation, quality)| {
+        elevation.to_le_bytes().into_iter().chain(std::iter::once(*quality))
+      })
+      .collect();
+    let buf = TiffBuilder::new()
+      .image(2, 1, 0, 0)
+      .shorts(TAG_SAMPLES_PER_PIXEL, &[2])
+      .shorts(TAG_BITS_PER_SAMPLE, &[32, 8])
+      .shorts(TAG_SAMPLE_FORMAT, &[3, 1])
+      .strips(1, vec![pixels])
+      .build();
+    let (ifd, _) = read(buf).unwrap();
+    let data = ifd.data.as_ref().unwrap();
+
+    assert_eq!(data.bands(), 2);
+    assert_eq!(data.get(0, 1, 0).unwrap(), Some(-3.25));
+    assert_eq!(data.get(1, 0, 0).unwrap(), Some(1.0));
+    assert_eq!(data.get(1, 1, 0).unwrap(), Some(2.0));
+  }
+
+  #[test]
+  fn test_read_bits_per_sample_mismatch() {
+    let buf = TiffBuilder::new()
+      .image(1, 1, 0, 1)
+      .shorts(TAG_SAMPLES_PER_PIXEL, &[3])
+      .shorts(TAG_BITS_PER_SAMPLE, &[8, 8])
+      .strips(1, vec![vec![0; 3]])
+      .build();
+
+    assert!(matches!(read(buf), Err(TiffParserError::InvalidValue(..))));
+  }
+
+  #[test]
+  fn test_huge_tile() {
+    // a tiny file declaring a 2**30 x 2**30 image in a single tile
+    let buf = TiffBuilder::new()
+      .image(16, 16, 16, 2)
+      .longs(TAG_IMAGE_WIDTH, &[1 << 30])
+      .longs(TAG_IMAGE_LENGTH, &[1 << 30])
+      .tiles(1 << 30, 1 << 30, vec![Compression::Deflate.compress(&[0; 64]).unwrap()])
+      .shorts(TAG_COMPRESSION, &[Compression::Deflate.to_u16()])
+      .build();
+
+    assert!(buf.len() < 300);
+    assert!(matches!(read(buf), Err(TiffParserError::BlockTooLarge(..))));
+  }
+}
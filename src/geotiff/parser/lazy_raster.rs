@@ -0,0 +1,583 @@
+use std::{
+  fmt,
+  ops::Range,
+  panic,
+  sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+  },
+  thread,
+};
+
+use once_cell::sync::OnceCell;
+
+use super::{
+  bounds::slice,
+  compression::create_decompressor,
+  endianness::Endianness,
+  predictor::Predictor,
+  raster::{Raster, SampleFormat},
+  source::Source,
+  TiffParserError,
+};
+
+/// Tile sides are multiples of this many pixels, so tiles stick out of large
+/// images by less than it.
+const TILE_ALIGNMENT: usize = 16;
+
+/// Largest tile side writers commonly use whatever the size of the image, such
+/// as 256 pixel tiles of a smaller image.
+const COMMON_TILE_SIZE: usize = 1024;
+
+/// Largest block decoded, in bytes. Block sizes come from the header, so this
+/// keeps a small malicious file from requesting an arbitrarily large allocation.
+const MAX_BLOCK_BYTES: usize = 1 << 30;
+
+/// Geometry and encoding of the strips or tiles an image is stored in.
+pub(super) struct BlockLayout {
+  pub(super) image_width: usize,
+  pub(super) image_length: usize,
+  pub(super) block_width: usize,
+  pub(super) block_length: usize,
+  /// Tiles are always stored full size, the last strip only holds the rows left.
+  pub(super) padded: bool,
+  /// Bits of each sample of a pixel, one entry per band.
+  pub(super) bits_per_sample: Vec<usize>,
+  pub(super) sample_formats: Vec<SampleFormat>,
+  /// Whether every band is stored in its own set of blocks (PlanarConfiguration = 2)
+  /// rather than interleaved within a pixel.
+  pub(super) planar: bool,
+  pub(super) compression: u16,
+  /// Compression applied on top of LERC, from the LercParameters tag.
+  pub(super) lerc_compression: u16,
+  pub(super) predictor: Predictor,
+  pub(super) offsets: Vec<u64>,
+  pub(super) byte_counts: Vec<u64>,
+}
+
+impl BlockLayout {
+  fn bands(&self) -> usize {
+    self.bits_per_sample.len()
+  }
+
+  fn blocks_across(&self) -> usize {
+    self.image_width.div_ceil(self.block_width)
+  }
+
+  fn blocks_down(&self) -> usize {
+    self.image_length.div_ceil(self.block_length)
+  }
+
+  fn blocks_per_plane(&self) -> usize {
+    self.blocks_across() * self.blocks_down()
+  }
+
+  /// Bands whose samples are stored in the block at `index`.
+  fn block_bands(&self, index: usize) -> Range<usize> {
+    if self.planar {
+      let band = index / self.blocks_per_plane();
+      band..band + 1
+    } else {
+      0..self.bands()
+    }
+  }
+}
+
+/// Decoded samples of a block.
+struct Block {
+  /// One raster per band stored in the block.
+  rasters: Vec<Raster>,
+  /// Pixels the codec marked as valid, if it stores a mask.
+  mask: Option<Vec<bool>>,
+  /// Lowest and highest value of the first band inside the image, voids
+  /// excluded. `None` for blocks of the other bands or holding only voids.
+  range: Option<(f64, f64)>,
+}
+
+/// Image whose strips or tiles are decompressed on first access and cached.
+pub struct LazyRaster {
+  layout: BlockLayout,
+  endianness: Endianness,
+  source: Arc<Source>,
+  blocks: Vec<OnceCell<Block>>,
+  /// Value marking voids, left out of the block ranges.
+  nodata: Option<f64>,
+}
+
+impl LazyRaster {
+  pub(super) fn new(
+    layout: BlockLayout,
+    endianness: Endianness,
+    source: Arc<Source>,
+  ) -> Result<Self, TiffParserError> {
+    // reject what can't be decoded up front rather than on the first query
+    for (format, bits) in layout.sample_formats.iter().zip(&layout.bits_per_sample) {
+      Raster::with_capacity(*format, *bits, 0)?;
+    }
+    create_decompressor(layout.compression, endianness, layout.lerc_compression)?;
+    if !layout.planar && layout.predictor != Predictor::None {
+      // predictors difference whole pixels, which needs samples of equal size
+      let first_bits = layout.bits_per_sample[0];
+      if let Some(bits) = layout.bits_per_sample.iter().find(|bits| **bits != first_bits) {
+        return Err(TiffParserError::UnsupportedSampleSize(*bits));
+      }
+    }
+    let pixel_bytes: usize = layout.bits_per_sample.iter().map(|bits| bits / 8).sum();
+    let block_bytes = layout.block_width
+      .checked_mul(layout.block_length)
+      .and_then(|pixels| pixels.checked_mul(pixel_bytes));
+    let fits = |block: usize, image: usize| {
+      block <= image
+        .checked_next_multiple_of(TILE_ALIGNMENT)
+        .unwrap_or(usize::MAX)
+        .max(COMMON_TILE_SIZE)
+    };
+    if !fits(layout.block_width, layout.image_width)
+      || !fits(layout.block_length, layout.image_length)
+      || block_bytes.is_none_or(|bytes| bytes > MAX_BLOCK_BYTES)
+    {
+      return Err(TiffParserError::BlockTooLarge(layout.block_width, layout.block_length));
+    }
+
+    let planes = if layout.planar { layout.bands() } else { 1 };
+    let block_count = layout.blocks_across()
+      .checked_mul(layout.blocks_down())
+      .and_then(|blocks| blocks.checked_mul(planes));
+    let stored = layout.offsets.len().min(layout.byte_counts.len());
+    let block_count = match block_count {
+      Some(count) if count <= stored => count,
+      count => return Err(TiffParserError::BlockCountMismatch(count.unwrap_or(usize::MAX), stored)),
+    };
+
+    Ok(Self {
+      layout,
+      endianness,
+      source,
+      blocks: (0..block_count).map(|_| OnceCell::new()).collect(),
+      nodata: None,
+    })
+  }
+
+  /// Sets the value marking voids. Must be called before any block is decoded.
+  pub(crate) fn set_nodata(&mut self, nodata: Option<f64>) {
+    self.nodata = nodata;
+  }
+
+  pub fn width(&self) -> usize {
+    self.layout.image_width
+  }
+
+  pub fn length(&self) -> usize {
+    self.layout.image_length
+  }
+
+  /// Number of samples per pixel.
+  pub fn bands(&self) -> usize {
+    self.layout.bands()
+  }
+
+  /// Returns sample `band` of the pixel at column `x` and row `y`, counted from
+  /// the top left corner, or `None` if it is outside the image or masked out
+  /// by the codec.
+  pub fn get(&self, band: usize, x: usize, y: usize) -> Result<Option<f64>, TiffParserError> {
+    let layout = &self.layout;
+    let index = match self.block_index(band, x, y) {
+      Some(index) => index,
+      None => return Ok(None),
+    };
+    let block = self.block(index)?;
+    let pixel = (y % layout.block_length) * layout.block_width + x % layout.block_width;
+    if block.mask.as_ref().is_some_and(|mask| !mask[pixel]) {
+      return Ok(None);
+    }
+    Ok(block.rasters[band - layout.block_bands(index).start].get(pixel))
+  }
+
+  /// Whether a sample read from `band` is the nodata value, compared at the
+  /// precision the samples are stored with.
+  pub(crate) fn is_nodata(&self, band: usize, value: f64, nodata: f64) -> bool {
+    if value.is_nan() || nodata.is_nan() {
+      return value.is_nan() && nodata.is_nan();
+    }
+    let format = self.layout.sample_formats.get(band);
+    let bits = self.layout.bits_per_sample.get(band);
+    match (format, bits) {
+      (Some(SampleFormat::Float), Some(32)) => value as f32 == nodata as f32,
+      _ => value == nodata,
+    }
+  }
+
+  /// Decodes every block not decoded yet on up to `threads` threads, so that
+  /// later queries only read cached samples.
+  pub fn decode_all(&self, threads: usize) -> Result<(), TiffParserError> {
+    self.decode_blocks(&(0..self.blocks.len()).collect::<Vec<_>>(), threads)
+  }
+
+  /// Decodes the blocks holding sample `band` of the pixels at `(x, y)` that are
+  /// not decoded yet on up to `threads` threads.
+  pub fn decode_pixels(
+    &self,
+    band: usize,
+    pixels: &[(usize, usize)],
+    threads: usize,
+  ) -> Result<(), TiffParserError> {
+    let mut indices: Vec<usize> = pixels
+      .iter()
+      .filter_map(|&(x, y)| self.block_index(band, x, y))
+      .filter(|index| self.blocks[*index].get().is_none())
+      .collect();
+    indices.sort_unstable();
+    indices.dedup();
+    self.decode_blocks(&indices, threads)
+  }
+
+  /// Index of the block holding sample `band` of the pixel at column `x` and
+  /// row `y`, `None` outside the image.
+  fn block_index(&self, band: usize, x: usize, y: usize) -> Option<usize> {
+    let layout = &self.layout;
+    if band >= layout.bands() || x >= layout.image_width || y >= layout.image_length {
+      return None;
+    }
+    let index = (y / layout.block_length) * layout.blocks_across() + x / layout.block_width;
+    match layout.planar {
+      true => Some(index + band * layout.blocks_per_plane()),
+      false => Some(index),
+    }
+  }
+
+  fn decode_blocks(&self, indices: &[usize], threads: usize) -> Result<(), TiffParserError> {
+    let next = AtomicUsize::new(0);
+    let worker = || loop {
+      let index = match indices.get(next.fetch_add(1, Ordering::Relaxed)) {
+        Some(index) => *index,
+        None => return Ok(()),
+      };
+      self.block(index)?;
+    };
+    let threads = threads.clamp(1, indices.len().max(1));
+    thread::scope(|scope| {
+      let workers: Vec<_> = (1..threads).map(|_| scope.spawn(worker)).collect();
+      workers
+        .into_iter()
+        .map(|handle| handle.join().unwrap_or_else(|err| panic::resume_unwind(err)))
+        .fold(worker(), Result::and)
+    })
+  }
+
+  /// Lowest and highest value of the first band, voids excluded. Folds the
+  /// ranges kept with the blocks, decoding the blocks not decoded yet.
+  pub(crate) fn range(&self) -> Result<Option<(f64, f64)>, TiffParserError> {
+    let mut range: Option<(f64, f64)> = None;
+    // the first band is in the first plane of planar images
+    for index in 0..self.layout.blocks_per_plane() {
+      if let Some((low, high)) = self.block(index)?.range {
+        range = Some(range.map_or((low, high), |(min, max)| (min.min(low), max.max(high))));
+      }
+    }
+    Ok(range)
+  }
+
+  /// Number of blocks decoded so far.
+  pub(crate) fn decoded_blocks(&self) -> usize {
+    self.blocks.iter().filter(|block| block.get().is_some()).count()
+  }
+
+  fn block(&self, index: usize) -> Result<&Block, TiffParserError> {
+    self.blocks[index].get_or_try_init(|| self.decode_block(index))
+  }
+
+  fn decode_block(&self, index: usize) -> Result<Block, TiffParserError> {
+    let layout = &self.layout;
+    let rows = if layout.padded {
+      layout.block_length
+    } else {
+      let first_row = (index % layout.blocks_per_plane() / layout.blocks_across())
+        * layout.block_length;
+      layout.block_length.min(layout.image_length - first_row)
+    };
+    let bands = layout.block_bands(index);
+    let sample_bytes: Vec<usize> = layout.bits_per_sample[bands.clone()]
+      .iter()
+      .map(|bits| bits / 8)
+      .collect();
+    let bytes_per_pixel: usize = sample_bytes.iter().sum();
+
+    let offset = layout.offsets[index] as usize;
+    let count = layout.byte_counts[index] as usize;
+    let encoded = slice(&self.source, offset, count)?;
+    let (mut block, mask) =
+      create_decompressor(layout.compression, self.endianness, layout.lerc_compression)?
+        .decompress_masked(encoded, rows * layout.block_width * bytes_per_pixel)?;
+    layout.predictor.undo(
+      self.endianness,
+      &mut block,
+      layout.block_width,
+      sample_bytes.len(),
+      sample_bytes[0],
+    )?;
+
+    let pixels = rows * layout.block_width;
+    let first_band = bands.start == 0;
+    let mut rasters = bands
+      .map(|band| {
+        Raster::with_capacity(layout.sample_formats[band], layout.bits_per_sample[band], pixels)
+      })
+      .collect::<Result<Vec<_>, TiffParserError>>()?;
+    for pixel in block.chunks_exact(bytes_per_pixel).take(pixels) {
+      let mut start = 0;
+      for (raster, size) in rasters.iter_mut().zip(&sample_bytes) {
+        raster.push(self.endianness, &pixel[start..])?;
+        start += size;
+      }
+    }
+    let range = match first_band {
+      true => self.block_range(index, &rasters[0], mask.as_deref()),
+      false => None,
+    };
+    Ok(Block { rasters, mask, range })
+  }
+
+  /// Lowest and highest value of the pixels of the block at `index` inside the
+  /// image, skipping voids and the pixels `mask` marks invalid.
+  fn block_range(
+    &self,
+    index: usize,
+    raster: &Raster,
+    mask: Option<&[bool]>,
+  ) -> Option<(f64, f64)> {
+    let layout = &self.layout;
+    let index = index % layout.blocks_per_plane();
+    let first_column = index % layout.blocks_across() * layout.block_width;
+    let first_row = index / layout.blocks_across() * layout.block_length;
+    let columns = layout.block_width.min(layout.image_width - first_column);
+    let rows = layout.block_length.min(layout.image_length - first_row);
+    let mut range: Option<(f64, f64)> = None;
+    for row in 0..rows {
+      for pixel in row * layout.block_width..row * layout.block_width + columns {
+        if mask.is_some_and(|mask| !mask[pixel]) {
+          continue;
+        }
+        let value = match raster.get(pixel) {
+          Some(value) => value,
+          None => continue,
+        };
+        if self.nodata.is_some_and(|nodata| self.is_nodata(0, value, nodata)) {
+          continue;
+        }
+        range = Some(range.map_or((value, value), |(min, max)| (min.min(value), max.max(value))));
+      }
+    }
+    range
+  }
+}
+
+impl fmt::Debug for LazyRaster {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.debug_struct("LazyRaster")
+      .field("width", &self.layout.image_width)
+      .field("length", &self.layout.image_length)
+      .field("block_width", &self.layout.block_width)
+      .field("block_length", &self.layout.block_length)
+      .field("bits_per_sample", &self.layout.bits_per_sample)
+      .field("sample_formats", &self.layout.sample_formats)
+      .field("planar", &self.layout.planar)
+      .field("blocks", &format_args!("{}/{} decoded", self.decoded_blocks(), self.blocks.len()))
+      .finish()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn layout(padded: bool, offsets: Vec<u64>, byte_counts: Vec<u64>) -> BlockLayout {
+    BlockLayout {
+      image_width: 3,
+      image_length: 3,
+      block_width: 2,
+      block_length: 2,
+      padded,
+      bits_per_sample: vec![8],
+      sample_formats: vec![SampleFormat::Uint],
+      planar: false,
+      compression: 1,
+      lerc_compression: 0,
+      predictor: Predictor::None,
+      offsets,
+      byte_counts,
+    }
+  }
+
+  #[test]
+  fn test_decodes_on_access() {
+    // 3x3 image in four padded 2x2 tiles
+    let bytes = vec![1, 2, 4, 5, 3, 0, 6, 0, 7, 8, 0, 0, 9, 0, 0, 0];
+    let source = Arc::new(Source::Memory(bytes));
+    let raster = LazyRaster::new(
+      layout(true, vec![0, 4, 8, 12], vec![4; 4]),
+      Endianness::LittleEndian,
+      source,
+    )
+    .unwrap();
+
+    assert_eq!(raster.decoded_blocks(), 0);
+    assert_eq!(raster.get(0, 2, 1).unwrap(), Some(6.0));
+    assert_eq!(raster.decoded_blocks(), 1);
+    assert_eq!(raster.get(0, 2, 0).unwrap(), Some(3.0));
+    assert_eq!(raster.decoded_blocks(), 1);
+    assert_eq!(raster.get(0, 0, 2).unwrap(), Some(7.0));
+    assert_eq!(raster.get(0, 2, 2).unwrap(), Some(9.0));
+    assert_eq!(raster.get(0, 3, 0).unwrap(), None);
+    assert_eq!(raster.decoded_blocks(), 3);
+  }
+
+  #[test]
+  fn test_short_last_strip() {
+    let bytes = vec![1, 2, 3, 4, 5, 6];
+    let source = Arc::new(Source::Memory(bytes));
+    let mut layout = layout(false, vec![0, 4], vec![4, 2]);
+    layout.image_width = 2;
+    let raster = LazyRaster::new(layout, Endianness::LittleEndian, source).unwrap();
+
+    assert_eq!(raster.get(0, 1, 2).unwrap(), Some(6.0));
+    assert_eq!(raster.get(0, 1, 1).unwrap(), Some(4.0));
+  }
+
+  #[test]
+  fn test_missing_blocks() {
+    let source = Arc::new(Source::Memory(vec![0; 16]));
+
+    assert!(matches!(
+      LazyRaster::new(layout(true, vec![0, 4], vec![4, 4]), Endianness::LittleEndian, source),
+      Err(TiffParserError::BlockCountMismatch(4, 2))
+    ));
+  }
+
+  #[test]
+  fn test_lerc_mask() {
+    // 2x2 i16 tile whose third pixel is invalid
+    let bytes = vec![
+      0x4c, 0x65, 0x72, 0x63, 0x32, 0x20, 0x04, 0x00, 0x00, 0x00, 0xb7, 0x03, 0x4e, 0xa7, 0x02,
+      0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x00,
+      0x08, 0x00, 0x00, 0x00, 0x56, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+      0x00, 0x00, 0x00, 0xe0, 0x3f, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xc0, 0x00, 0x00,
+      0x00, 0x00, 0x00, 0xc0, 0x92, 0x40, 0x05, 0x00, 0x00, 0x00, 0x01, 0x00, 0xdf, 0x00, 0x80,
+      0xfe, 0xff, 0xb0, 0x04, 0x01, 0xfe, 0xff, 0xb0, 0x04, 0x0f, 0x00,
+    ];
+    let source = Arc::new(Source::Memory(bytes));
+    let mut layout = layout(true, vec![0], vec![86]);
+    layout.image_width = 2;
+    layout.image_length = 2;
+    layout.bits_per_sample = vec![16];
+    layout.sample_formats = vec![SampleFormat::Int];
+    layout.compression = 34887;
+    let raster = LazyRaster::new(layout, Endianness::LittleEndian, source).unwrap();
+
+    assert_eq!(raster.get(0, 0, 0).unwrap(), Some(-2.0));
+    assert_eq!(raster.get(0, 1, 0).unwrap(), Some(1200.0));
+    assert_eq!(raster.get(0, 0, 1).unwrap(), None);
+    assert_eq!(raster.get(0, 1, 1).unwrap(), Some(15.0));
+  }
+
+  #[test]
+  fn test_block_too_large() {
+    let source = || Arc::new(Source::Memory(vec![0; 16]));
+    // tiles may be larger than a small image
+    let mut tile = layout(true, vec![0], vec![4]);
+    tile.block_width = 256;
+    tile.block_length = 256;
+    assert!(LazyRaster::new(tile, Endianness::LittleEndian, source()).is_ok());
+
+    let mut tile = layout(true, vec![0], vec![4]);
+    tile.block_width = 1 << 30;
+    tile.block_length = 1 << 30;
+    assert!(matches!(
+      LazyRaster::new(tile, Endianness::LittleEndian, source()),
+      Err(TiffParserError::BlockTooLarge(_, _))
+    ));
+    // but not by more than the 16 pixels tiles are sized in for a large one
+    let mut tile = layout(true, vec![0], vec![4]);
+    (tile.image_width, tile.image_length) = (2000, 2000);
+    (tile.block_width, tile.block_length) = (2016, 2032);
+    assert!(matches!(
+      LazyRaster::new(tile, Endianness::LittleEndian, source()),
+      Err(TiffParserError::BlockTooLarge(_, _))
+    ));
+    // a single strip of a huge image
+    let mut strip = layout(false, vec![0], vec![4]);
+    (strip.image_width, strip.image_length) = (1 << 20, 1 << 20);
+    (strip.block_width, strip.block_length) = (1 << 20, 1 << 20);
+    assert!(matches!(
+      LazyRaster::new(strip, Endianness::LittleEndian, source()),
+      Err(TiffParserError::BlockTooLarge(_, _))
+    ));
+  }
+
+  #[test]
+  fn test_decode_all() {
+    let bytes = vec![1, 2, 4, 5, 3, 0, 6, 0, 7, 8, 0, 0, 9, 0, 0, 0];
+    let source = Arc::new(Source::Memory(bytes));
+    let raster = LazyRaster::new(
+      layout(true, vec![0, 4, 8, 12], vec![4; 4]),
+      Endianness::LittleEndian,
+      source,
+    )
+    .unwrap();
+    raster.get(0, 0, 0).unwrap();
+    raster.decode_all(3).unwrap();
+
+    assert_eq!(raster.decoded_blocks(), 4);
+    assert_eq!(raster.get(0, 1, 1).unwrap(), Some(5.0));
+    assert_eq!(raster.get(0, 2, 2).unwrap(), Some(9.0));
+  }
+
+  #[test]
+  fn test_decode_pixels() {
+    let bytes = vec![1, 2, 4, 5, 3, 0, 6, 0, 7, 8, 0, 0, 9, 0, 0, 0];
+    let source = Arc::new(Source::Memory(bytes));
+    let raster = LazyRaster::new(
+      layout(true, vec![0, 4, 8, 12], vec![4; 4]),
+      Endianness::LittleEndian,
+      source,
+    )
+    .unwrap();
+    // the first two pixels share a block, the last one is outside the image
+    raster.decode_pixels(0, &[(0, 0), (1, 1), (2, 2), (3, 0)], 4).unwrap();
+
+    assert_eq!(raster.decoded_blocks(), 2);
+    assert_eq!(raster.get(0, 2, 2).unwrap(), Some(9.0));
+  }
+
+  #[test]
+  fn test_range() {
+    let bytes = vec![1, 2, 4, 5, 3, 0, 6, 0, 7, 8, 0, 0, 9, 0, 0, 0];
+    let source = Arc::new(Source::Memory(bytes));
+    let mut raster = LazyRaster::new(
+      layout(true, vec![0, 4, 8, 12], vec![4; 4]),
+      Endianness::LittleEndian,
+      source,
+    )
+    .unwrap();
+    // the zero padding of the tiles is outside the image
+    assert_eq!(raster.range().unwrap(), Some((1.0, 9.0)));
+    assert_eq!(raster.decoded_blocks(), 4);
+
+    raster.blocks.iter_mut().for_each(|block| drop(block.take()));
+    raster.set_nodata(Some(9.0));
+    assert_eq!(raster.range().unwrap(), Some((1.0, 8.0)));
+  }
+
+  #[test]
+  fn test_decode_all_error() {
+    let source = Arc::new(Source::Memory(vec![0; 12]));
+    let raster = LazyRaster::new(
+      layout(true, vec![0, 4, 8, 12], vec![4; 4]),
+      Endianness::LittleEndian,
+      source,
+    )
+    .unwrap();
+
+    assert!(matches!(raster.decode_all(2), Err(TiffParserError::Truncated(12, 4, 12))));
+    assert_eq!(raster.decoded_blocks(), 3);
+  }
+}
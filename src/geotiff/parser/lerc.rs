@@ -0,0 +1,573 @@
+//! Decoder for LERC2 blobs (Limited Error Raster Compression), versions 2 to 4,
+//! as written into TIFF strips and tiles by libtiff and GDAL.
+
+use super::{
+  bounds::{slice, zeroed},
+  endianness::Endianness,
+  TiffParserError,
+};
+
+const FILE_KEY: &[u8] = b"Lerc2 ";
+const MAX_VERSION: i32 = 4;
+/// Bytes of the file key, version and checksum, which the checksum doesn't cover.
+const CHECKSUM_START: usize = 14;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum DataType {
+  Char,
+  Byte,
+  Short,
+  UShort,
+  Int,
+  UInt,
+  Float,
+  Double,
+}
+
+impl DataType {
+  fn from_i32(data_type: i32) -> Result<Self, TiffParserError> {
+    match data_type {
+      0 => Ok(DataType::Char),
+      1 => Ok(DataType::Byte),
+      2 => Ok(DataType::Short),
+      3 => Ok(DataType::UShort),
+      4 => Ok(DataType::Int),
+      5 => Ok(DataType::UInt),
+      6 => Ok(DataType::Float),
+      7 => Ok(DataType::Double),
+      _ => Err(TiffParserError::InvalidLerc("unknown data type")),
+    }
+  }
+
+  fn size(self) -> usize {
+    match self {
+      DataType::Char | DataType::Byte => 1,
+      DataType::Short | DataType::UShort => 2,
+      DataType::Int | DataType::UInt | DataType::Float => 4,
+      DataType::Double => 8,
+    }
+  }
+
+  /// Type a tile offset is stored with, which may be narrower than the data.
+  fn narrowed(self, code: u8) -> Result<Self, TiffParserError> {
+    let data_type = match (self, code) {
+      (_, 0) => self,
+      (DataType::Short | DataType::Int, _) => DataType::from_i32(self as i32 - code as i32)?,
+      (DataType::UShort | DataType::UInt, _) => {
+        DataType::from_i32(self as i32 - 2 * code as i32)?
+      }
+      (DataType::Float, 1) => DataType::Short,
+      (DataType::Float, _) => DataType::Byte,
+      (DataType::Double, _) => DataType::from_i32(self as i32 - 2 * code as i32 + 1)?,
+      _ => self,
+    };
+    Ok(data_type)
+  }
+
+  /// Reads a little-endian value, as LERC stores them.
+  fn read(self, bytes: &[u8]) -> f64 {
+    let mut buf = [0; 8];
+    buf[..bytes.len()].copy_from_slice(bytes);
+    match self {
+      DataType::Char => bytes[0] as i8 as f64,
+      DataType::Byte => bytes[0] as f64,
+      DataType::Short => i16::from_le_bytes([buf[0], buf[1]]) as f64,
+      DataType::UShort => u16::from_le_bytes([buf[0], buf[1]]) as f64,
+      DataType::Int => i32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]) as f64,
+      DataType::UInt => u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]) as f64,
+      DataType::Float => f32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]) as f64,
+      DataType::Double => f64::from_le_bytes(buf),
+    }
+  }
+
+  fn write(self, value: f64, endianness: Endianness, buf: &mut Vec<u8>) {
+    macro_rules! write {
+      ($type:ty) => {{
+        let value = value as $type;
+        match endianness {
+          Endianness::LittleEndian => buf.extend_from_slice(&value.to_le_bytes()),
+          Endianness::BigEndian => buf.extend_from_slice(&value.to_be_bytes()),
+        }
+      }};
+    }
+    match self {
+      DataType::Char => write!(i8),
+      DataType::Byte => write!(u8),
+      DataType::Short => write!(i16),
+      DataType::UShort => write!(u16),
+      DataType::Int => write!(i32),
+      DataType::UInt => write!(u32),
+      DataType::Float => write!(f32),
+      DataType::Double => write!(f64),
+    }
+  }
+}
+
+struct Cursor<'a> {
+  buf: &'a [u8],
+  pos: usize,
+}
+
+impl<'a> Cursor<'a> {
+  fn new(buf: &'a [u8]) -> Self {
+    Self { buf, pos: 0 }
+  }
+
+  fn take(&mut self, len: usize) -> Result<&'a [u8], TiffParserError> {
+    let bytes = slice(self.buf, self.pos, len)?;
+    self.pos += len;
+    Ok(bytes)
+  }
+
+  fn u8(&mut self) -> Result<u8, TiffParserError> {
+    Ok(self.take(1)?[0])
+  }
+
+  fn i16(&mut self) -> Result<i16, TiffParserError> {
+    Ok(i16::from_le_bytes(self.take(2)?.try_into()?))
+  }
+
+  fn i32(&mut self) -> Result<i32, TiffParserError> {
+    Ok(i32::from_le_bytes(self.take(4)?.try_into()?))
+  }
+
+  fn u32(&mut self) -> Result<u32, TiffParserError> {
+    Ok(u32::from_le_bytes(self.take(4)?.try_into()?))
+  }
+
+  fn f64(&mut self) -> Result<f64, TiffParserError> {
+    Ok(f64::from_le_bytes(self.take(8)?.try_into()?))
+  }
+
+  /// Reads a non-negative count or size.
+  fn size(&mut self) -> Result<usize, TiffParserError> {
+    usize::try_from(self.i32()?).map_err(|_| TiffParserError::InvalidLerc("negative size"))
+  }
+
+  fn value(&mut self, data_type: DataType) -> Result<f64, TiffParserError> {
+    Ok(data_type.read(self.take(data_type.size())?))
+  }
+}
+
+struct Header {
+  version: i32,
+  rows: usize,
+  columns: usize,
+  depth: usize,
+  valid_pixels: usize,
+  micro_block_size: usize,
+  data_type: DataType,
+  max_z_error: f64,
+  z_min: f64,
+  z_max: f64,
+}
+
+impl Header {
+  fn read(cursor: &mut Cursor) -> Result<Self, TiffParserError> {
+    if cursor.take(FILE_KEY.len())? != FILE_KEY {
+      return Err(TiffParserError::InvalidLerc("missing Lerc2 file key"));
+    }
+    let version = cursor.i32()?;
+    if !(2..=MAX_VERSION).contains(&version) {
+      return Err(TiffParserError::UnsupportedLerc("only versions 2 to 4 can be decoded"));
+    }
+    let checksum = if version >= 3 { Some(cursor.u32()?) } else { None };
+    let rows = cursor.size()?;
+    let columns = cursor.size()?;
+    let depth = if version >= 4 { cursor.size()? } else { 1 };
+    let valid_pixels = cursor.size()?;
+    let micro_block_size = cursor.size()?;
+    let blob_size = cursor.size()?;
+    let data_type = DataType::from_i32(cursor.i32()?)?;
+    let header = Self {
+      version,
+      rows,
+      columns,
+      depth,
+      valid_pixels,
+      micro_block_size,
+      data_type,
+      max_z_error: cursor.f64()?,
+      z_min: cursor.f64()?,
+      z_max: cursor.f64()?,
+    };
+
+    if rows == 0 || columns == 0 || depth == 0 || micro_block_size == 0 {
+      return Err(TiffParserError::InvalidLerc("empty image or micro block"));
+    }
+    let blob = slice(cursor.buf, 0, blob_size)?;
+    if let Some(checksum) = checksum {
+      if blob_size < CHECKSUM_START || fletcher32(&blob[CHECKSUM_START..]) != checksum {
+        return Err(TiffParserError::InvalidLerc("checksum mismatch"));
+      }
+    }
+    // nothing past the blob belongs to it
+    cursor.buf = blob;
+    Ok(header)
+  }
+
+  fn pixels(&self) -> usize {
+    self.rows * self.columns
+  }
+
+  /// Whether 8-bit data is followed by a flag telling if it is Huffman-coded.
+  fn may_use_huffman(&self) -> bool {
+    matches!(self.data_type, DataType::Char | DataType::Byte) && self.max_z_error == 0.5
+  }
+}
+
+/// Decodes a blob of `size` bytes once decompressed into samples of its own
+/// data type in `endianness` byte order, interleaved per pixel, along with
+/// which pixels are valid. Invalid pixels are left as zero.
+pub(super) fn decode(
+  blob: &[u8],
+  endianness: Endianness,
+  size: usize,
+) -> Result<(Vec<u8>, Vec<bool>), TiffParserError> {
+  let mut cursor = Cursor::new(blob);
+  let header = Header::read(&mut cursor)?;
+  let decoded_size = header
+    .pixels()
+    .checked_mul(header.depth)
+    .and_then(|samples| samples.checked_mul(header.data_type.size()));
+  if decoded_size != Some(size) {
+    return Err(TiffParserError::InvalidLerc("image size doesn't match the block"));
+  }
+
+  let mask = read_mask(&mut cursor, &header)?;
+  let values = read_values(&mut cursor, &header, &mask)?;
+  let mut bytes = vec![];
+  bytes.try_reserve_exact(size)?;
+  for value in values {
+    header.data_type.write(value, endianness, &mut bytes);
+  }
+  Ok((bytes, mask))
+}
+
+/// Reads the run-length encoded bit mask of valid pixels, most significant bit first.
+fn read_mask(cursor: &mut Cursor, header: &Header) -> Result<Vec<bool>, TiffParserError> {
+  let pixels = header.pixels();
+  let len = cursor.size()?;
+  let mut rle = Cursor::new(cursor.take(len)?);
+  match header.valid_pixels {
+    0 => return Ok(vec![false; pixels]),
+    valid if valid == pixels => return Ok(vec![true; pixels]),
+    valid if valid > pixels => return Err(TiffParserError::InvalidLerc("too many valid pixels")),
+    _ => {}
+  }
+
+  let mut bits = Vec::with_capacity(pixels.div_ceil(8));
+  loop {
+    match rle.i16()? {
+      i16::MIN => break,
+      count if count > 0 => bits.extend_from_slice(rle.take(count as usize)?),
+      count => {
+        let byte = rle.u8()?;
+        bits.resize(bits.len() + count.unsigned_abs() as usize, byte);
+      }
+    }
+    if bits.len() > pixels.div_ceil(8) {
+      return Err(TiffParserError::InvalidLerc("mask is larger than the image"));
+    }
+  }
+  if bits.len() < pixels.div_ceil(8) {
+    return Err(TiffParserError::InvalidLerc("mask is smaller than the image"));
+  }
+  Ok((0..pixels).map(|k| bits[k >> 3] & (0x80 >> (k & 7)) != 0).collect())
+}
+
+fn read_values(
+  cursor: &mut Cursor,
+  header: &Header,
+  mask: &[bool],
+) -> Result<Vec<f64>, TiffParserError> {
+  let depth = header.depth;
+  let mut values = zeroed(header.pixels() * depth)?;
+  if header.valid_pixels == 0 {
+    return Ok(values);
+  }
+  let fill = |values: &mut Vec<f64>, constants: &[f64]| {
+    for (k, _) in mask.iter().enumerate().filter(|(_, valid)| **valid) {
+      values[k * depth..(k + 1) * depth].copy_from_slice(constants);
+    }
+  };
+  if header.z_min == header.z_max {
+    fill(&mut values, &vec![header.z_min; depth]);
+    return Ok(values);
+  }
+
+  // from version 4 on, the range of each sample of a pixel is stored separately
+  let mut z_max = vec![header.z_max; depth];
+  if header.version >= 4 {
+    let z_min = (0..depth)
+      .map(|_| cursor.value(header.data_type))
+      .collect::<Result<Vec<_>, TiffParserError>>()?;
+    for z in z_max.iter_mut() {
+      *z = cursor.value(header.data_type)?;
+    }
+    if z_min == z_max {
+      fill(&mut values, &z_min);
+      return Ok(values);
+    }
+  }
+
+  let raw = cursor.u8()? != 0;
+  if raw {
+    for (k, _) in mask.iter().enumerate().filter(|(_, valid)| **valid) {
+      for value in &mut values[k * depth..(k + 1) * depth] {
+        *value = cursor.value(header.data_type)?;
+      }
+    }
+    return Ok(values);
+  }
+  if header.may_use_huffman() && cursor.u8()? != 0 {
+    return Err(TiffParserError::UnsupportedLerc("Huffman-coded 8-bit data"));
+  }
+
+  let size = header.micro_block_size;
+  for i0 in (0..header.rows).step_by(size) {
+    for j0 in (0..header.columns).step_by(size) {
+      let rows = i0..(i0 + size).min(header.rows);
+      let columns = j0..(j0 + size).min(header.columns);
+      for (band, z_max) in z_max.iter().enumerate() {
+        let pixels: Vec<usize> = rows
+          .clone()
+          .flat_map(|i| columns.clone().map(move |j| i * header.columns + j))
+          .filter(|k| mask[*k])
+          .collect();
+        let samples = pixels.iter().map(|k| k * depth + band);
+        read_tile(cursor, header, j0, *z_max, samples, rows.len() * columns.len(), &mut values)?;
+      }
+    }
+  }
+  Ok(values)
+}
+
+/// Reads the valid samples of one micro block, whose indexes in `values` are `samples`.
+fn read_tile(
+  cursor: &mut Cursor,
+  header: &Header,
+  j0: usize,
+  z_max: f64,
+  samples: impl ExactSizeIterator<Item = usize>,
+  pixels: usize,
+  values: &mut [f64],
+) -> Result<(), TiffParserError> {
+  let flag = cursor.u8()?;
+  if (flag >> 2) & 15 != ((j0 >> 3) & 15) as u8 {
+    return Err(TiffParserError::InvalidLerc("corrupted micro block"));
+  }
+  match flag & 3 {
+    // all zero
+    2 => {
+      for k in samples {
+        values[k] = 0.0;
+      }
+    }
+    // uncompressed
+    0 => {
+      for k in samples {
+        values[k] = cursor.value(header.data_type)?;
+      }
+    }
+    encoding => {
+      let offset = cursor.value(header.data_type.narrowed(flag >> 6)?)?;
+      if encoding == 3 {
+        for k in samples {
+          values[k] = offset;
+        }
+        return Ok(());
+      }
+      let quantized = read_bit_stuffed(cursor, pixels, header.version)?;
+      if quantized.len() != samples.len() {
+        return Err(TiffParserError::InvalidLerc("micro block has the wrong sample count"));
+      }
+      let scale = 2.0 * header.max_z_error;
+      for (k, q) in samples.zip(quantized) {
+        values[k] = (offset + q as f64 * scale).min(z_max);
+      }
+    }
+  }
+  Ok(())
+}
+
+/// Reads up to `max_count` unsigned integers packed with as few bits as
+/// needed, optionally as indexes into a lookup table.
+fn read_bit_stuffed(
+  cursor: &mut Cursor,
+  max_count: usize,
+  version: i32,
+) -> Result<Vec<u32>, TiffParserError> {
+  let flags = cursor.u8()?;
+  let bits = (flags & 31) as usize;
+  let count = match flags >> 6 {
+    0 => cursor.u32()? as usize,
+    1 => u16::from_le_bytes(cursor.take(2)?.try_into()?) as usize,
+    2 => cursor.u8()? as usize,
+    _ => return Err(TiffParserError::InvalidLerc("invalid element count size")),
+  };
+  if count > max_count {
+    return Err(TiffParserError::InvalidLerc("too many elements in micro block"));
+  }
+  if flags & 32 == 0 {
+    return unstuff(cursor, count, bits, version);
+  }
+
+  // the table is stored without its leading zero
+  let table_len = cursor.u8()? as usize;
+  if bits == 0 || table_len < 2 {
+    return Err(TiffParserError::InvalidLerc("empty lookup table"));
+  }
+  let mut table = unstuff(cursor, table_len - 1, bits, version)?;
+  table.insert(0, 0);
+  let index_bits = (usize::BITS - (table_len - 1).leading_zeros()) as usize;
+  unstuff(cursor, count, index_bits, version)?
+    .into_iter()
+    .map(|index| table.get(index as usize).copied())
+    .collect::<Option<Vec<_>>>()
+    .ok_or(TiffParserError::InvalidLerc("lookup table index out of range"))
+}
+
+fn unstuff(
+  cursor: &mut Cursor,
+  count: usize,
+  bits: usize,
+  version: i32,
+) -> Result<Vec<u32>, TiffParserError> {
+  if bits == 0 {
+    return Ok(vec![0; count]);
+  }
+  let bytes = cursor.take((count * bits).div_ceil(8))?;
+  let mask = (1u64 << bits) - 1;
+  if version >= 3 {
+    // least significant bit first
+    let read = |start: usize| {
+      let window = bytes.iter().skip(start / 8).take(8).rev().fold(0, |w, b| w << 8 | *b as u64);
+      ((window >> (start % 8)) & mask) as u32
+    };
+    return Ok((0..count).map(|i| read(i * bits)).collect());
+  }
+
+  // before version 3, most significant bit first within little-endian words,
+  // the bytes of the last partial word being its most significant ones
+  let mut words = Vec::with_capacity(bytes.len().div_ceil(4) * 4);
+  for chunk in bytes.chunks(4) {
+    let mut word = [0; 4];
+    word[4 - chunk.len()..].copy_from_slice(chunk);
+    words.extend(u32::from_le_bytes(word).to_be_bytes());
+  }
+  let read = |start: usize| {
+    let window = words.iter().skip(start / 8).take(8).fold(0, |w, b| w << 8 | *b as u64);
+    let window_bits = 8 * words.len().saturating_sub(start / 8).min(8);
+    ((window >> (window_bits - start % 8 - bits)) & mask) as u32
+  };
+  Ok((0..count).map(|i| read(i * bits)).collect())
+}
+
+fn fletcher32(bytes: &[u8]) -> u32 {
+  let mut sum1: u32 = 0xffff;
+  let mut sum2: u32 = 0xffff;
+  let words = bytes.chunks_exact(2);
+  let straggler = words.remainder().first().copied();
+  // reduce often enough for the sums not to overflow
+  for block in words.collect::<Vec<_>>().chunks(359) {
+    for word in block {
+      sum1 += (word[0] as u32) << 8 | word[1] as u32;
+      sum2 += sum1;
+    }
+    sum1 = (sum1 & 0xffff) + (sum1 >> 16);
+    sum2 = (sum2 & 0xffff) + (sum2 >> 16);
+  }
+  if let Some(byte) = straggler {
+    sum1 += (byte as u32) << 8;
+    sum2 += sum1;
+  }
+  sum1 = (sum1 & 0xffff) + (sum1 >> 16);
+  sum2 = (sum2 & 0xffff) + (sum2 >> 16);
+  sum2 << 16 | sum1
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // 4x4 i16 tile, bit stuffed as version 2 did, most significant bit first
+  const SHORTS_V2: [u8; 81] = [
+    0x4c, 0x65, 0x72, 0x63, 0x32, 0x20, 0x02, 0x00, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x04, 0x00,
+    0x00, 0x00, 0x10, 0x00, 0x00, 0x00, 0x08, 0x00, 0x00, 0x00, 0x51, 0x00, 0x00, 0x00, 0x02, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xe0, 0x3f, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x34, 0xc0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x40, 0x55, 0x40, 0x00, 0x00, 0x00, 0x00, 0x00, 0x81,
+    0xec, 0x87, 0x10, 0x53, 0x71, 0x1c, 0x00, 0x70, 0x31, 0xd5, 0x88, 0x96, 0xda, 0x34, 0xfe, 0x69,
+    0xf1,
+  ];
+
+  // the same tile as version 3, least significant bit first
+  const SHORTS_V3: [u8; 85] = [
+    0x4c, 0x65, 0x72, 0x63, 0x32, 0x20, 0x03, 0x00, 0x00, 0x00, 0x9e, 0xe8, 0x17, 0x99, 0x04, 0x00,
+    0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x10, 0x00, 0x00, 0x00, 0x08, 0x00, 0x00, 0x00, 0x55, 0x00,
+    0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xe0, 0x3f, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x34, 0xc0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x40, 0x55, 0x40, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x81, 0xec, 0x87, 0x10, 0x80, 0x83, 0xa3, 0xc2, 0x19, 0xa9, 0x62, 0xb8, 0x9f,
+    0xb1, 0x49, 0xdd, 0x8a, 0xd3,
+  ];
+
+  // 3x2 f32 tile whose fourth pixel is invalid, stored uncompressed
+  const FLOATS_MASKED: [u8; 104] = [
+    0x4c, 0x65, 0x72, 0x63, 0x32, 0x20, 0x04, 0x00, 0x00, 0x00, 0x05, 0x7f, 0x75, 0x1a, 0x02, 0x00,
+    0x00, 0x00, 0x03, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x05, 0x00, 0x00, 0x00, 0x08, 0x00,
+    0x00, 0x00, 0x68, 0x00, 0x00, 0x00, 0x06, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0xc0, 0x00, 0x00, 0x00, 0x20, 0x6e, 0x48,
+    0xc1, 0x40, 0x05, 0x00, 0x00, 0x00, 0x01, 0x00, 0xef, 0x00, 0x80, 0x00, 0x00, 0x10, 0xc0, 0x71,
+    0x43, 0x0a, 0x46, 0x01, 0x00, 0x00, 0xc0, 0x3f, 0x00, 0x00, 0x10, 0xc0, 0x00, 0x00, 0xc8, 0x42,
+    0x00, 0x00, 0xf8, 0x40, 0x71, 0x43, 0x0a, 0x46,
+  ];
+
+  fn decode_i16(bytes: &[u8]) -> Vec<i16> {
+    bytes
+      .chunks(2)
+      .map(|b| i16::from_le_bytes([b[0], b[1]]))
+      .collect()
+  }
+
+  #[test]
+  fn test_bit_stuffing() {
+    let pixels: Vec<i16> = (0..16).map(|x| x * 7 - 20).collect();
+    for blob in [&SHORTS_V2[..], &SHORTS_V3[..]] {
+      let (bytes, mask) = decode(blob, Endianness::LittleEndian, 32).unwrap();
+
+      assert_eq!(decode_i16(&bytes), pixels);
+      assert!(mask.iter().all(|valid| *valid));
+    }
+  }
+
+  #[test]
+  fn test_mask() {
+    let (bytes, mask) = decode(&FLOATS_MASKED, Endianness::BigEndian, 24).unwrap();
+    let floats: Vec<f32> = bytes
+      .chunks(4)
+      .map(|b| f32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+      .collect();
+
+    assert_eq!(mask, vec![true, true, true, false, true, true]);
+    assert_eq!(floats, vec![1.5, -2.25, 100.0, 0.0, 7.75, 8848.86]);
+  }
+
+  #[test]
+  fn test_invalid() {
+    let mut corrupted = SHORTS_V3;
+    corrupted[70] ^= 1;
+
+    assert!(matches!(
+      decode(&corrupted, Endianness::LittleEndian, 32),
+      Err(TiffParserError::InvalidLerc("checksum mismatch"))
+    ));
+    assert!(matches!(
+      decode(&SHORTS_V3, Endianness::LittleEndian, 16),
+      Err(TiffParserError::InvalidLerc(_))
+    ));
+    assert!(matches!(
+      decode(&SHORTS_V3[..40], Endianness::LittleEndian, 32),
+      Err(TiffParserError::Truncated(..))
+    ));
+  }
+}
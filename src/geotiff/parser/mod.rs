@@ -1,11 +1,23 @@
+mod bounds;
 mod compression;
 mod endianness;
 mod error;
 mod field;
 mod ifd;
-mod tags;
+mod lazy_raster;
+mod lerc;
+mod predictor;
+mod raster;
+mod source;
+pub(super) mod tags;
+#[cfg(test)]
+pub(crate) mod testing;
 mod tiff_file;
 mod value;
+mod variant;
 
+pub use compression::{compression_name, Compression};
 pub use error::TiffParserError;
-pub use tiff_file::TiffFile;
\ No newline at end of file
+pub use ifd::{Ifd, IfdEntry};
+pub use tiff_file::TiffFile;
+pub use value::Value;
\ No newline at end of file
@@ -0,0 +1,184 @@
+use super::{endianness::Endianness, TiffParserError};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) enum Predictor {
+  None = 1,
+  Horizontal = 2,
+  FloatingPoint = 3,
+}
+
+impl Predictor {
+  pub(super) fn from_u16(predictor: u16) -> Result<Self, TiffParserError> {
+    match predictor {
+      1 => Ok(Predictor::None),
+      2 => Ok(Predictor::Horizontal),
+      3 => Ok(Predictor::FloatingPoint),
+      predictor => Err(TiffParserError::UnknownPredictor(predictor)),
+    }
+  }
+
+  /// Reverses the predictor in place for a decompressed block of `width` pixels per row.
+  pub(super) fn undo(
+    &self,
+    endianness: Endianness,
+    buf: &mut [u8],
+    width: usize,
+    samples_per_pixel: usize,
+    bytes_per_sample: usize,
+  ) -> Result<(), TiffParserError> {
+    let row_size = width * samples_per_pixel * bytes_per_sample;
+    if row_size == 0 {
+      return Ok(());
+    }
+    let rows = buf.chunks_exact_mut(row_size);
+    match self {
+      Predictor::None => Ok(()),
+      Predictor::Horizontal => {
+        for row in rows {
+          undo_horizontal(endianness, row, samples_per_pixel, bytes_per_sample)?;
+        }
+        Ok(())
+      }
+      Predictor::FloatingPoint => {
+        for row in rows {
+          undo_floating_point(endianness, row, samples_per_pixel, bytes_per_sample);
+        }
+        Ok(())
+      }
+    }
+  }
+}
+
+fn undo_horizontal(
+  endianness: Endianness,
+  row: &mut [u8],
+  samples_per_pixel: usize,
+  bytes_per_sample: usize,
+) -> Result<(), TiffParserError> {
+  let stride = samples_per_pixel * bytes_per_sample;
+  match bytes_per_sample {
+    1 => {
+      for i in stride..row.len() {
+        row[i] = row[i].wrapping_add(row[i - stride]);
+      }
+    }
+    2 => {
+      for i in (stride..row.len()).step_by(2) {
+        let value = endianness
+          .read_u16(&row[i..])?
+          .wrapping_add(endianness.read_u16(&row[i - stride..])?);
+        endianness.write_u16(value, &mut row[i..]);
+      }
+    }
+    4 => {
+      for i in (stride..row.len()).step_by(4) {
+        let value = endianness
+          .read_u32(&row[i..])?
+          .wrapping_add(endianness.read_u32(&row[i - stride..])?);
+        endianness.write_u32(value, &mut row[i..]);
+      }
+    }
+    bytes_per_sample => {
+      return Err(TiffParserError::UnsupportedSampleSize(bytes_per_sample * 8));
+    }
+  }
+  Ok(())
+}
+
+// The floating point predictor (Adobe Photoshop TIFF Technical Note 3) differences
+// bytes, not samples, after splitting every row into planes from the most to the
+// least significant byte.
+fn undo_floating_point(
+  endianness: Endianness,
+  row: &mut [u8],
+  samples_per_pixel: usize,
+  bytes_per_sample: usize,
+) {
+  for i in samples_per_pixel..row.len() {
+    row[i] = row[i].wrapping_add(row[i - samples_per_pixel]);
+  }
+
+  let count = row.len() / bytes_per_sample;
+  let planes = row.to_vec();
+  for i in 0..count {
+    for byte in 0..bytes_per_sample {
+      let plane = match endianness {
+        Endianness::BigEndian => byte,
+        Endianness::LittleEndian => bytes_per_sample - byte - 1,
+      };
+      row[bytes_per_sample * i + byte] = planes[plane * count + i];
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_horizontal_i16() {
+    let deltas: [i16; 8] = [100, -3, 5, 0, -32768, 1, 1, 1];
+    let mut buf: Vec<u8> = deltas.iter().flat_map(|x| x.to_le_bytes()).collect();
+    Predictor::Horizontal
+      .undo(Endianness::LittleEndian, &mut buf, 4, 1, 2)
+      .unwrap();
+    let values: Vec<i16> = buf
+      .chunks(2)
+      .map(|b| i16::from_le_bytes([b[0], b[1]]))
+      .collect();
+
+    assert_eq!(values, vec![100, 97, 102, 102, -32768, -32767, -32766, -32765]);
+  }
+
+  #[test]
+  fn test_horizontal_u32_big_endian() {
+    let deltas: [u32; 3] = [u32::MAX, 2, 10];
+    let mut buf: Vec<u8> = deltas.iter().flat_map(|x| x.to_be_bytes()).collect();
+    Predictor::Horizontal
+      .undo(Endianness::BigEndian, &mut buf, 3, 1, 4)
+      .unwrap();
+    let values: Vec<u32> = buf
+      .chunks(4)
+      .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+      .collect();
+
+    assert_eq!(values, vec![u32::MAX, 1, 11]);
+  }
+
+  #[test]
+  fn test_floating_point_f32() {
+    let heights: [f32; 3] = [101.25, -0.5, 8848.86];
+
+    // encode the way libtiff does: big-endian byte planes, then byte differencing
+    let count = heights.len();
+    let mut encoded = vec![0u8; count * 4];
+    for (i, height) in heights.iter().enumerate() {
+      for (byte, value) in height.to_be_bytes().iter().enumerate() {
+        encoded[byte * count + i] = *value;
+      }
+    }
+    for i in (1..encoded.len()).rev() {
+      encoded[i] = encoded[i].wrapping_sub(encoded[i - 1]);
+    }
+
+    let mut le = encoded.clone();
+    Predictor::FloatingPoint
+      .undo(Endianness::LittleEndian, &mut le, 3, 1, 4)
+      .unwrap();
+    let values: Vec<f32> = le
+      .chunks(4)
+      .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+      .collect();
+    assert_eq!(values, heights.to_vec());
+
+    let mut be = encoded;
+    Predictor::FloatingPoint
+      .undo(Endianness::BigEndian, &mut be, 3, 1, 4)
+      .unwrap();
+    let values: Vec<f32> = be
+      .chunks(4)
+      .map(|b| f32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+      .collect();
+    assert_eq!(values, heights.to_vec());
+  }
+}
@@ -0,0 +1,135 @@
+use std::{collections::TryReserveError, fmt};
+
+use super::{endianness::Endianness, TiffParserError};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SampleFormat {
+  Uint = 1,
+  Int = 2,
+  Float = 3,
+}
+
+impl SampleFormat {
+  pub(super) fn from_u16(format: u16) -> Result<Self, TiffParserError> {
+    match format {
+      1 => Ok(SampleFormat::Uint),
+      2 => Ok(SampleFormat::Int),
+      3 => Ok(SampleFormat::Float),
+      format => Err(TiffParserError::UnknownSampleFormat(format)),
+    }
+  }
+}
+
+/// Decoded samples of an image, stored in their native type.
+#[derive(Clone, PartialEq)]
+pub enum Raster {
+  Int8(Vec<i8>),
+  Int16(Vec<i16>),
+  Int32(Vec<i32>),
+  Uint8(Vec<u8>),
+  Uint16(Vec<u16>),
+  Uint32(Vec<u32>),
+  Float32(Vec<f32>),
+  Float64(Vec<f64>),
+}
+
+impl Raster {
+  pub(super) fn with_capacity(
+    format: SampleFormat,
+    bits_per_sample: usize,
+    capacity: usize,
+  ) -> Result<Self, TiffParserError> {
+    let mut raster = match (format, bits_per_sample) {
+      (SampleFormat::Int, 8) => Raster::Int8(vec![]),
+      (SampleFormat::Int, 16) => Raster::Int16(vec![]),
+      (SampleFormat::Int, 32) => Raster::Int32(vec![]),
+      (SampleFormat::Uint, 8) => Raster::Uint8(vec![]),
+      (SampleFormat::Uint, 16) => Raster::Uint16(vec![]),
+      (SampleFormat::Uint, 32) => Raster::Uint32(vec![]),
+      (SampleFormat::Float, 32) => Raster::Float32(vec![]),
+      (SampleFormat::Float, 64) => Raster::Float64(vec![]),
+      (_, bits_per_sample) => return Err(TiffParserError::UnsupportedSampleSize(bits_per_sample)),
+    };
+    raster.try_reserve(capacity)?;
+    Ok(raster)
+  }
+
+  /// Reserves room for `additional` samples, failing rather than aborting when
+  /// the memory isn't available.
+  fn try_reserve(&mut self, additional: usize) -> Result<(), TryReserveError> {
+    match self {
+      Raster::Int8(vals) => vals.try_reserve_exact(additional),
+      Raster::Int16(vals) => vals.try_reserve_exact(additional),
+      Raster::Int32(vals) => vals.try_reserve_exact(additional),
+      Raster::Uint8(vals) => vals.try_reserve_exact(additional),
+      Raster::Uint16(vals) => vals.try_reserve_exact(additional),
+      Raster::Uint32(vals) => vals.try_reserve_exact(additional),
+      Raster::Float32(vals) => vals.try_reserve_exact(additional),
+      Raster::Float64(vals) => vals.try_reserve_exact(additional),
+    }
+  }
+
+  /// Decodes one sample from the start of `bytes` and appends it.
+  pub(super) fn push(
+    &mut self,
+    endianness: Endianness,
+    bytes: &[u8],
+  ) -> Result<(), TiffParserError> {
+    match self {
+      Raster::Int8(vals) => vals.push(bytes[0] as i8),
+      Raster::Int16(vals) => vals.push(endianness.read_i16(bytes)?),
+      Raster::Int32(vals) => vals.push(endianness.read_i32(bytes)?),
+      Raster::Uint8(vals) => vals.push(bytes[0]),
+      Raster::Uint16(vals) => vals.push(endianness.read_u16(bytes)?),
+      Raster::Uint32(vals) => vals.push(endianness.read_u32(bytes)?),
+      Raster::Float32(vals) => vals.push(endianness.read_f32(bytes)?),
+      Raster::Float64(vals) => vals.push(endianness.read_f64(bytes)?),
+    }
+    Ok(())
+  }
+
+  pub(crate) fn len(&self) -> usize {
+    match self {
+      Raster::Int8(vals) => vals.len(),
+      Raster::Int16(vals) => vals.len(),
+      Raster::Int32(vals) => vals.len(),
+      Raster::Uint8(vals) => vals.len(),
+      Raster::Uint16(vals) => vals.len(),
+      Raster::Uint32(vals) => vals.len(),
+      Raster::Float32(vals) => vals.len(),
+      Raster::Float64(vals) => vals.len(),
+    }
+  }
+
+  /// Returns the sample at `index`, widened to `f64` so no precision is lost.
+  pub fn get(&self, index: usize) -> Option<f64> {
+    match self {
+      Raster::Int8(vals) => vals.get(index).map(|x| *x as f64),
+      Raster::Int16(vals) => vals.get(index).map(|x| *x as f64),
+      Raster::Int32(vals) => vals.get(index).map(|x| *x as f64),
+      Raster::Uint8(vals) => vals.get(index).map(|x| *x as f64),
+      Raster::Uint16(vals) => vals.get(index).map(|x| *x as f64),
+      Raster::Uint32(vals) => vals.get(index).map(|x| *x as f64),
+      Raster::Float32(vals) => vals.get(index).map(|x| *x as f64),
+      Raster::Float64(vals) => vals.get(index).cloned(),
+    }
+  }
+}
+
+impl fmt::Debug for Raster {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let name = match self {
+      Raster::Int8(_) => "Int8",
+      Raster::Int16(_) => "Int16",
+      Raster::Int32(_) => "Int32",
+      Raster::Uint8(_) => "Uint8",
+      Raster::Uint16(_) => "Uint16",
+      Raster::Uint32(_) => "Uint32",
+      Raster::Float32(_) => "Float32",
+      Raster::Float64(_) => "Float64",
+    };
+    f.debug_tuple(name)
+      .field(&format_args!("{} pixels", self.len()))
+      .finish()
+  }
+}
@@ -0,0 +1,36 @@
+use std::{fs::File, ops::Deref, path::Path};
+
+use memmap2::Mmap;
+
+use super::TiffParserError;
+
+/// Bytes a TIFF file is parsed from, kept alive for as long as its rasters
+/// may still decode blocks out of it.
+pub(super) enum Source {
+  Memory(Vec<u8>),
+  Mapped(Mmap),
+}
+
+impl Source {
+  /// Maps the file into memory. The file must not be truncated or rewritten
+  /// while the mapping is alive, or reading it faults with SIGBUS.
+  pub(super) fn map<P: AsRef<Path>>(name: P) -> Result<Self, TiffParserError> {
+    let file = File::open(name)?;
+    // SAFETY: not guaranteed here; callers of `TiffFile::from_file` promise that
+    // the file stays as it is. The tile cache, which downloads rewrite, is read
+    // into memory instead.
+    let mmap = unsafe { Mmap::map(&file)? };
+    Ok(Source::Mapped(mmap))
+  }
+}
+
+impl Deref for Source {
+  type Target = [u8];
+
+  fn deref(&self) -> &[u8] {
+    match self {
+      Source::Memory(bytes) => bytes,
+      Source::Mapped(mmap) => mmap,
+    }
+  }
+}
@@ -1,20 +1,84 @@
-pub(super) const TAG_IMAGE_WIDTH: u16 = 256;
-pub(super) const TAG_IMAGE_LENGTH: u16 = 257;
+pub const TAG_NEW_SUBFILE_TYPE: u16 = 254;
 
-pub(super) const TAG_BITS_PER_SAMPLE: u16 = 258;
+pub const TAG_IMAGE_WIDTH: u16 = 256;
+pub const TAG_IMAGE_LENGTH: u16 = 257;
 
-pub(super) const TAG_COMPRESSION: u16 = 259;
+pub const TAG_BITS_PER_SAMPLE: u16 = 258;
 
-pub(super) const TAG_STRIP_OFFSETS: u16 = 273;
+pub const TAG_COMPRESSION: u16 = 259;
 
-pub(super) const TAG_SAMPLES_PER_PIXEL: u16 = 277;
+pub const TAG_STRIP_OFFSETS: u16 = 273;
 
-pub(super) const TAG_ROWS_PER_STRIP: u16 = 278;
-pub(super) const TAG_STRIP_BYTE_COUNTS: u16 = 279;
+pub const TAG_SAMPLES_PER_PIXEL: u16 = 277;
 
-pub(super) const TAG_TILE_WIDTH: u16 = 322;
-pub(super) const TAG_TILE_LENGTH: u16 = 323;
-pub(super) const TAG_TILE_OFFSETS: u16 = 324;
-pub(super) const TAG_TILE_BYTE_COUNTS: u16 = 325;
+pub const TAG_ROWS_PER_STRIP: u16 = 278;
+pub const TAG_STRIP_BYTE_COUNTS: u16 = 279;
 
-pub(super) const TAG_SUB_IFDS: u16 = 330;
\ No newline at end of file
+pub const TAG_PLANAR_CONFIGURATION: u16 = 284;
+
+pub const TAG_PREDICTOR: u16 = 317;
+
+pub const TAG_TILE_WIDTH: u16 = 322;
+pub const TAG_TILE_LENGTH: u16 = 323;
+pub const TAG_TILE_OFFSETS: u16 = 324;
+pub const TAG_TILE_BYTE_COUNTS: u16 = 325;
+
+pub const TAG_SUB_IFDS: u16 = 330;
+
+pub const TAG_SAMPLE_FORMAT: u16 = 339;
+pub const TAG_LERC_PARAMETERS: u16 = 50674;
+
+/// Name of a baseline TIFF, GeoTIFF or GDAL tag, as spelled in its specification.
+pub fn tag_name(tag: u16) -> Option<&'static str> {
+  let name = match tag {
+    TAG_NEW_SUBFILE_TYPE => "NewSubfileType",
+    255 => "SubfileType",
+    TAG_IMAGE_WIDTH => "ImageWidth",
+    TAG_IMAGE_LENGTH => "ImageLength",
+    TAG_BITS_PER_SAMPLE => "BitsPerSample",
+    TAG_COMPRESSION => "Compression",
+    262 => "PhotometricInterpretation",
+    266 => "FillOrder",
+    269 => "DocumentName",
+    270 => "ImageDescription",
+    271 => "Make",
+    272 => "Model",
+    TAG_STRIP_OFFSETS => "StripOffsets",
+    274 => "Orientation",
+    TAG_SAMPLES_PER_PIXEL => "SamplesPerPixel",
+    TAG_ROWS_PER_STRIP => "RowsPerStrip",
+    TAG_STRIP_BYTE_COUNTS => "StripByteCounts",
+    280 => "MinSampleValue",
+    281 => "MaxSampleValue",
+    282 => "XResolution",
+    283 => "YResolution",
+    TAG_PLANAR_CONFIGURATION => "PlanarConfiguration",
+    296 => "ResolutionUnit",
+    305 => "Software",
+    306 => "DateTime",
+    315 => "Artist",
+    TAG_PREDICTOR => "Predictor",
+    320 => "ColorMap",
+    TAG_TILE_WIDTH => "TileWidth",
+    TAG_TILE_LENGTH => "TileLength",
+    TAG_TILE_OFFSETS => "TileOffsets",
+    TAG_TILE_BYTE_COUNTS => "TileByteCounts",
+    TAG_SUB_IFDS => "SubIFDs",
+    338 => "ExtraSamples",
+    TAG_SAMPLE_FORMAT => "SampleFormat",
+    340 => "SMinSampleValue",
+    341 => "SMaxSampleValue",
+    33432 => "Copyright",
+    33550 => "ModelPixelScaleTag",
+    33922 => "ModelTiepointTag",
+    34264 => "ModelTransformationTag",
+    34735 => "GeoKeyDirectoryTag",
+    34736 => "GeoDoubleParamsTag",
+    34737 => "GeoAsciiParamsTag",
+    42112 => "GDAL_METADATA",
+    42113 => "GDAL_NODATA",
+    TAG_LERC_PARAMETERS => "LercParameters",
+    _ => return None,
+  };
+  Some(name)
+}
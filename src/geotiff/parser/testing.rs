@@ -0,0 +1,200 @@
+//! Builder for small in-memory TIFF files used by the tests.
+
+use super::tags::*;
+
+struct Entry {
+  tag: u16,
+  field: u16,
+  count: usize,
+  bytes: Vec<u8>,
+}
+
+/// Little-endian classic TIFF or BigTIFF with a single image and optional overviews.
+pub(crate) struct TiffBuilder {
+  entries: Vec<Entry>,
+  chunks: Vec<Vec<u8>>,
+  offsets_tag: u16,
+  byte_counts_tag: u16,
+  overviews: Vec<TiffBuilder>,
+  big: bool,
+}
+
+/// `value` as a little-endian count or offset, 8 bytes wide in a BigTIFF.
+fn offset(value: usize, big: bool) -> Vec<u8> {
+  match big {
+    true => (value as u64).to_le_bytes().to_vec(),
+    false => (value as u32).to_le_bytes().to_vec(),
+  }
+}
+
+impl TiffBuilder {
+  pub(crate) fn new() -> Self {
+    Self {
+      entries: vec![],
+      chunks: vec![],
+      offsets_tag: TAG_STRIP_OFFSETS,
+      byte_counts_tag: TAG_STRIP_BYTE_COUNTS,
+      overviews: vec![],
+      big: false,
+    }
+  }
+
+  /// Writes a BigTIFF, with LONG8 offsets and byte counts for the image data.
+  pub(crate) fn big(mut self) -> Self {
+    self.big = true;
+    self
+  }
+
+  fn entry(mut self, tag: u16, field: u16, count: usize, bytes: Vec<u8>) -> Self {
+    self.entries.retain(|entry| entry.tag != tag);
+    self.entries.push(Entry { tag, field, count, bytes });
+    self
+  }
+
+  pub(crate) fn shorts(self, tag: u16, vals: &[u16]) -> Self {
+    let bytes = vals.iter().flat_map(|x| x.to_le_bytes()).collect();
+    self.entry(tag, 3, vals.len(), bytes)
+  }
+
+  pub(crate) fn longs(self, tag: u16, vals: &[u32]) -> Self {
+    let bytes = vals.iter().flat_map(|x| x.to_le_bytes()).collect();
+    self.entry(tag, 4, vals.len(), bytes)
+  }
+
+  pub(crate) fn long8s(self, tag: u16, vals: &[u64]) -> Self {
+    let bytes = vals.iter().flat_map(|x| x.to_le_bytes()).collect();
+    self.entry(tag, 16, vals.len(), bytes)
+  }
+
+  pub(crate) fn doubles(self, tag: u16, vals: &[f64]) -> Self {
+    let bytes = vals.iter().flat_map(|x| x.to_le_bytes()).collect();
+    self.entry(tag, 12, vals.len(), bytes)
+  }
+
+  pub(crate) fn ascii(self, tag: u16, val: &str) -> Self {
+    let mut bytes = val.as_bytes().to_vec();
+    bytes.push(0);
+    let count = bytes.len();
+    self.entry(tag, 2, count, bytes)
+  }
+
+  /// Single-band, uncompressed image header.
+  pub(crate) fn image(self, width: u16, length: u16, bits_per_sample: u16, format: u16) -> Self {
+    self
+      .shorts(TAG_IMAGE_WIDTH, &[width])
+      .shorts(TAG_IMAGE_LENGTH, &[length])
+      .shorts(TAG_BITS_PER_SAMPLE, &[bits_per_sample])
+      .shorts(TAG_COMPRESSION, &[1])
+      .shorts(TAG_SAMPLES_PER_PIXEL, &[1])
+      .shorts(TAG_SAMPLE_FORMAT, &[format])
+  }
+
+  pub(crate) fn strips(mut self, rows_per_strip: u32, chunks: Vec<Vec<u8>>) -> Self {
+    self.offsets_tag = TAG_STRIP_OFFSETS;
+    self.byte_counts_tag = TAG_STRIP_BYTE_COUNTS;
+    self.chunks = chunks;
+    self.longs(TAG_ROWS_PER_STRIP, &[rows_per_strip])
+  }
+
+  pub(crate) fn tiles(mut self, tile_width: u32, tile_length: u32, chunks: Vec<Vec<u8>>) -> Self {
+    self.offsets_tag = TAG_TILE_OFFSETS;
+    self.byte_counts_tag = TAG_TILE_BYTE_COUNTS;
+    self.chunks = chunks;
+    self
+      .longs(TAG_TILE_WIDTH, &[tile_width])
+      .longs(TAG_TILE_LENGTH, &[tile_length])
+  }
+
+  /// Appends a reduced-resolution image to the main IFD chain.
+  pub(crate) fn overview(mut self, overview: TiffBuilder) -> Self {
+    self.overviews.push(overview.longs(TAG_NEW_SUBFILE_TYPE, &[1]));
+    self
+  }
+
+  pub(crate) fn build(mut self) -> Vec<u8> {
+    let big = self.big;
+    let mut buf = match big {
+      true => b"II+\0\x08\0\0\0".to_vec(),
+      false => b"II*\0".to_vec(),
+    };
+    buf.extend(offset(buf.len() + offset(0, big).len(), big));
+    let overviews = std::mem::take(&mut self.overviews);
+    let mut next = self.write(&mut buf, big);
+    for overview in overviews {
+      let start = offset(buf.len(), big);
+      buf[next..next + start.len()].copy_from_slice(&start);
+      next = overview.write(&mut buf, big);
+    }
+    buf
+  }
+
+  /// Writes the IFD, its values and image data at the end of `buf` and returns
+  /// the position of its next IFD offset.
+  fn write(self, buf: &mut Vec<u8>, big: bool) -> usize {
+    let byte_counts: Vec<u64> = self.chunks.iter().map(|c| c.len() as u64).collect();
+    let placeholder = vec![0; self.chunks.len()];
+    let (offsets_tag, byte_counts_tag) = (self.offsets_tag, self.byte_counts_tag);
+    let chunks = self.chunks.clone();
+    let mut builder = self;
+    if !chunks.is_empty() {
+      builder = match big {
+        true => builder
+          .long8s(offsets_tag, &placeholder)
+          .long8s(byte_counts_tag, &byte_counts),
+        false => builder
+          .longs(offsets_tag, &placeholder.iter().map(|&x| x as u32).collect::<Vec<_>>())
+          .longs(byte_counts_tag, &byte_counts.iter().map(|&x| x as u32).collect::<Vec<_>>()),
+      };
+    }
+    let mut entries = builder.entries;
+    entries.sort_by_key(|entry| entry.tag);
+
+    let offset_size = offset(0, big).len();
+    let (count_size, entry_size) = match big {
+      true => (8, 20),
+      false => (2, 12),
+    };
+    let ifd_start = buf.len();
+    let payload_start = ifd_start + count_size + entries.len() * entry_size + offset_size;
+    let payload_size: usize = entries
+      .iter()
+      .filter(|entry| entry.bytes.len() > offset_size)
+      .map(|entry| entry.bytes.len())
+      .sum();
+    let mut start = payload_start + payload_size;
+    let offsets: Vec<u8> = chunks
+      .iter()
+      .flat_map(|chunk| {
+        let chunk_start = start;
+        start += chunk.len();
+        offset(chunk_start, big)
+      })
+      .collect();
+    if let Some(entry) = entries.iter_mut().find(|entry| entry.tag == offsets_tag) {
+      entry.bytes = offsets;
+    }
+
+    buf.extend_from_slice(&offset(entries.len(), big)[..count_size]);
+    let mut payload = vec![];
+    for entry in &entries {
+      buf.extend_from_slice(&entry.tag.to_le_bytes());
+      buf.extend_from_slice(&entry.field.to_le_bytes());
+      buf.extend(offset(entry.count, big));
+      if entry.bytes.len() > offset_size {
+        buf.extend(offset(payload_start + payload.len(), big));
+        payload.extend_from_slice(&entry.bytes);
+      } else {
+        let mut inline = entry.bytes.clone();
+        inline.resize(offset_size, 0);
+        buf.extend_from_slice(&inline);
+      }
+    }
+    let next = buf.len();
+    buf.extend(offset(0, big));
+    buf.extend(payload);
+    for chunk in chunks {
+      buf.extend(chunk);
+    }
+    next
+  }
+}
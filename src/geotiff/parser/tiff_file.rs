@@ -1,6 +1,13 @@
-use std::{fs::File, io::Read, path::Path};
+use std::{cmp::Reverse, collections::HashSet, iter, mem, path::Path, sync::Arc};
 
-use super::{endianness::Endianness, ifd::Ifd, TiffParserError};
+use super::{
+  bounds::{slice, tail},
+  endianness::Endianness,
+  ifd::Ifd,
+  source::Source,
+  variant::TiffVariant,
+  TiffParserError,
+};
 
 #[derive(Debug)]
 pub struct TiffFile {
@@ -8,16 +15,45 @@ pub struct TiffFile {
 }
 
 impl TiffFile {
+  /// Maps the file into memory; image data is only decoded when it is read. The
+  /// file must not be modified while the `TiffFile` or rasters taken from it are
+  /// alive.
   pub fn from_file<P: AsRef<Path>>(name: P) -> Result<Self, TiffParserError> {
-    let mut file = File::open(name)?;
-    let mut buffer = vec![];
-    file.read_to_end(&mut buffer)?;
-
-    Self::from_bytes(&buffer)
+    Self::from_source(Source::map(name)?)
   }
 
   pub fn from_bytes(buf: &[u8]) -> Result<TiffFile, TiffParserError> {
-    let endianness = match &buf[0..2] {
+    Self::from_source(Source::Memory(buf.to_vec()))
+  }
+
+  /// IFDs of the main chain, in file order.
+  pub fn ifds(&self) -> &[Ifd] {
+    &self.ifds
+  }
+
+  /// Consumes the file into its full resolution image followed by its overviews,
+  /// finest first. Overviews are taken from both the main IFD chain and the
+  /// SubIFDs of the first image; masks and other pages are skipped.
+  pub(crate) fn into_pyramid(self) -> Vec<Ifd> {
+    let mut ifds = self.ifds.into_iter();
+    let mut full = match ifds.next() {
+      Some(ifd) => ifd,
+      None => return vec![],
+    };
+    let mut overviews: Vec<Ifd> = mem::take(&mut full.sub_ifds)
+      .into_iter()
+      .chain(ifds)
+      .filter(|ifd| ifd.data.is_some() && ifd.is_reduced_resolution() && !ifd.is_mask())
+      .collect();
+    overviews.sort_by_key(|ifd| Reverse(ifd.data.as_ref().map_or(0, |data| data.width())));
+
+    iter::once(full).chain(overviews).collect()
+  }
+
+  fn from_source(source: Source) -> Result<TiffFile, TiffParserError> {
+    let source = Arc::new(source);
+    let buf: &[u8] = &source;
+    let endianness = match slice(buf, 0, 2)? {
       b"II" => Endianness::LittleEndian,
       b"MM" => Endianness::BigEndian,
       marker => {
@@ -25,11 +61,23 @@ impl TiffFile {
       }
     };
 
-    let mut next_ifd_offset = endianness.read_u32(&buf[4..])? as usize;
+    let variant = TiffVariant::from_u16(endianness.read_u16(tail(buf, 2)?)?)?;
+    let mut next_ifd_offset = match variant {
+      TiffVariant::Classic => variant.read_offset(endianness, tail(buf, 4)?)?,
+      TiffVariant::Big => {
+        let offset_size = endianness.read_u16(tail(buf, 4)?)?;
+        if offset_size != 8 {
+          return Err(TiffParserError::UnsupportedOffsetSize(offset_size));
+        }
+        variant.read_offset(endianness, tail(buf, 8)?)?
+      }
+    };
     let mut ifds = vec![];
+    let mut visited = HashSet::new();
 
     while next_ifd_offset != 0 {
-      let (ifd, offset) = Ifd::read(endianness, buf, next_ifd_offset)?;
+      let (ifd, offset) =
+        Ifd::read(endianness, variant, &source, next_ifd_offset, &mut visited, 0)?;
       ifds.push(ifd);
       next_ifd_offset = offset;
     }
@@ -38,4 +86,65 @@ impl TiffFile {
 
     Ok(tiff)
   }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::geotiff::parser::testing::TiffBuilder;
+
+  /// Builds a little-endian BigTIFF with a 2x2 Int16 image split into two strips.
+  fn big_tiff(pixels: &[i16; 4]) -> Vec<u8> {
+    let rows = pixels
+      .chunks(2)
+      .map(|row| row.iter().flat_map(|p| p.to_le_bytes()).collect())
+      .collect();
+    TiffBuilder::new().big().image(2, 2, 16, 2).strips(1, rows).build()
+  }
+
+  #[test]
+  fn test_big_tiff() {
+    let tiff = TiffFile::from_bytes(&big_tiff(&[-32768, 12, 250, 8848])).unwrap();
+
+    assert_eq!(tiff.ifds.len(), 1);
+    let data = tiff.ifds[0].data.as_ref().unwrap();
+    assert_eq!(data.get(0, 0, 0).unwrap(), Some(-32768.0));
+    assert_eq!(data.get(0, 1, 1).unwrap(), Some(8848.0));
+  }
+
+  #[test]
+  fn test_unknown_magic() {
+    let mut buf = big_tiff(&[0; 4]);
+    buf[2] = 44;
+
+    assert!(matches!(TiffFile::from_bytes(&buf), Err(TiffParserError::UnknownMagic(44))));
+  }
+
+  #[test]
+  fn test_truncated() {
+    let buf = big_tiff(&[1, 2, 3, 4]);
+
+    for len in 0..buf.len() {
+      if let Ok(tiff) = TiffFile::from_bytes(&buf[..len]) {
+        let data = tiff.ifds[0].data.as_ref().unwrap();
+        let _ = data.get(0, 1, 1);
+      }
+    }
+    assert!(matches!(TiffFile::from_bytes(&buf[..1]), Err(TiffParserError::Truncated(0, 2, 1))));
+    // the header is intact, but the last strip is cut short
+    let tiff = TiffFile::from_bytes(&buf[..buf.len() - 1]).unwrap();
+    let data = tiff.ifds[0].data.as_ref().unwrap();
+    assert_eq!(data.get(0, 0, 0).unwrap(), Some(1.0));
+    assert!(matches!(data.get(0, 1, 1), Err(TiffParserError::Truncated(..))));
+  }
+
+  #[test]
+  fn test_ifd_loop() {
+    let mut buf = big_tiff(&[0; 4]);
+    // point the next IFD offset, after the 9 entries, back at the first IFD
+    let next = 16 + 8 + 9 * 20;
+    buf[next..next + 8].copy_from_slice(&16u64.to_le_bytes());
+
+    assert!(matches!(TiffFile::from_bytes(&buf), Err(TiffParserError::IfdLoop(16))));
+  }
+}
@@ -10,6 +10,9 @@ pub enum Value {
   Sshorts(Vec<i16>),
   Longs(Vec<u32>),
   Slongs(Vec<i32>),
+  Long8s(Vec<u64>),
+  Slong8s(Vec<i64>),
+  Ifd8s(Vec<u64>),
   Rationals(Vec<(u32, u32)>),
   Srationals(Vec<(i32, i32)>),
   Floats(Vec<f32>),
@@ -41,45 +44,59 @@ impl Value {
       }
       Field::Short => {
         let vals: Result<Vec<_>, _> =
-          buf.chunks(2).map(|b| endianness.read_u16(b)).collect();
+          buf.chunks_exact(2).map(|b| endianness.read_u16(b)).collect();
         Ok(Value::Shorts(vals?))
       }
       Field::Sshort => {
         let vals: Result<Vec<_>, _> =
-          buf.chunks(2).map(|b| endianness.read_i16(b)).collect();
+          buf.chunks_exact(2).map(|b| endianness.read_i16(b)).collect();
         Ok(Value::Sshorts(vals?))
       }
       Field::Long => {
         let vals: Result<Vec<_>, _> =
-          buf.chunks(4).map(|b| endianness.read_u32(b)).collect();
+          buf.chunks_exact(4).map(|b| endianness.read_u32(b)).collect();
         Ok(Value::Longs(vals?))
       }
       Field::Slong => {
         let vals: Result<Vec<_>, _> =
-          buf.chunks(4).map(|b| endianness.read_i32(b)).collect();
+          buf.chunks_exact(4).map(|b| endianness.read_i32(b)).collect();
         Ok(Value::Slongs(vals?))
       }
+      Field::Long8 => {
+        let vals: Result<Vec<_>, _> =
+          buf.chunks_exact(8).map(|b| endianness.read_u64(b)).collect();
+        Ok(Value::Long8s(vals?))
+      }
+      Field::Slong8 => {
+        let vals: Result<Vec<_>, _> =
+          buf.chunks_exact(8).map(|b| endianness.read_i64(b)).collect();
+        Ok(Value::Slong8s(vals?))
+      }
+      Field::Ifd8 => {
+        let vals: Result<Vec<_>, _> =
+          buf.chunks_exact(8).map(|b| endianness.read_u64(b)).collect();
+        Ok(Value::Ifd8s(vals?))
+      }
       Field::Rational => {
         let vals: Result<Vec<_>, _> = buf
-          .chunks(8)
+          .chunks_exact(8)
           .map(|b| try_tuple(endianness.read_u32(&b[0..]), endianness.read_u32(&b[4..])))
           .collect();
         Ok(Value::Rationals(vals?))
       }
       Field::Srational => {
         let vals: Result<Vec<_>, _> = buf
-          .chunks(8)
+          .chunks_exact(8)
           .map(|b| try_tuple(endianness.read_i32(&b[0..]), endianness.read_i32(&b[4..])))
           .collect();
         Ok(Value::Srationals(vals?))
       }
       Field::Ascii => {
-        let bytes = buf.to_vec();
-        let len = bytes.len();
-        if bytes[len - 1] != 0 {
-          return Err(TiffParserError::StringNotNullTerminated(bytes));
+        let mut bytes = buf.to_vec();
+        if bytes.pop() != Some(0) {
+          return Err(TiffParserError::StringNotNullTerminated(buf.to_vec()));
         }
-        Ok(Value::Ascii(String::from_utf8(bytes[0..len - 1].to_vec())?))
+        Ok(Value::Ascii(String::from_utf8(bytes)?))
       }
       Field::Undefined => {
         let bytes = buf.to_vec();
@@ -87,14 +104,14 @@ impl Value {
       }
       Field::Float => {
         let vals: Result<Vec<_>, _> = buf
-          .chunks(4)
+          .chunks_exact(4)
           .map(|b| endianness.read_f32(&b[0..]))
           .collect();
         Ok(Value::Floats(vals?))
       }
       Field::Double => {
         let vals: Result<Vec<_>, _> = buf
-          .chunks(8)
+          .chunks_exact(8)
           .map(|b| endianness.read_f64(&b[0..]))
           .collect();
         Ok(Value::Doubles(vals?))
@@ -104,18 +121,30 @@ impl Value {
 
   pub fn short(&self) -> Result<u16, TiffParserError> {
     match self {
-      Value::Shorts(vals) => Ok(vals[0]),
+      Value::Shorts(vals) if !vals.is_empty() => Ok(vals[0]),
       val => Err(TiffParserError::InvalidValue(val.clone(), "expected short")),
     }
   }
 
   pub fn long(&self) -> Result<u32, TiffParserError> {
     match self {
-      Value::Longs(vals) => Ok(vals[0]),
+      Value::Longs(vals) if !vals.is_empty() => Ok(vals[0]),
       val => Err(TiffParserError::InvalidValue(val.clone(), "expected long")),
     }
   }
 
+  pub fn uint(&self) -> Result<u64, TiffParserError> {
+    match self {
+      Value::Shorts(vals) if !vals.is_empty() => Ok(vals[0] as u64),
+      Value::Longs(vals) if !vals.is_empty() => Ok(vals[0] as u64),
+      Value::Long8s(vals) | Value::Ifd8s(vals) if !vals.is_empty() => Ok(vals[0]),
+      val => Err(TiffParserError::InvalidValue(
+        val.clone(),
+        "expected short, long or long8",
+      )),
+    }
+  }
+
   pub fn shorts(&self) -> Result<&[u16], TiffParserError> {
     match self {
       Value::Shorts(vals) => Ok(vals),
@@ -132,6 +161,32 @@ impl Value {
       val => Err(TiffParserError::InvalidValue(val.clone(), "expected longs")),
     }
   }
+
+  pub fn doubles(&self) -> Result<&[f64], TiffParserError> {
+    match self {
+      Value::Doubles(vals) => Ok(vals),
+      val => Err(TiffParserError::InvalidValue(val.clone(), "expected doubles")),
+    }
+  }
+
+  pub fn ascii(&self) -> Result<&str, TiffParserError> {
+    match self {
+      Value::Ascii(string) => Ok(string),
+      val => Err(TiffParserError::InvalidValue(val.clone(), "expected ascii")),
+    }
+  }
+
+  pub fn uints(&self) -> Result<Vec<u64>, TiffParserError> {
+    match self {
+      Value::Shorts(vals) => Ok(vals.iter().map(|v| *v as u64).collect()),
+      Value::Longs(vals) => Ok(vals.iter().map(|v| *v as u64).collect()),
+      Value::Long8s(vals) | Value::Ifd8s(vals) => Ok(vals.clone()),
+      val => Err(TiffParserError::InvalidValue(
+        val.clone(),
+        "expected shorts, longs or long8s",
+      )),
+    }
+  }
 }
 
 const MAX_LEN: usize = 226;
@@ -193,6 +248,33 @@ impl fmt::Debug for Value {
             .finish()
         }
       }
+      Value::Long8s(vals) => {
+        if vals.len() < MAX_LEN {
+          f.debug_tuple("Long8s").field(vals).finish()
+        } else {
+          f.debug_tuple("Long8s")
+            .field(&format_args!("{} values", vals.len()))
+            .finish()
+        }
+      }
+      Value::Slong8s(vals) => {
+        if vals.len() < MAX_LEN {
+          f.debug_tuple("Slong8s").field(vals).finish()
+        } else {
+          f.debug_tuple("Slong8s")
+            .field(&format_args!("{} values", vals.len()))
+            .finish()
+        }
+      }
+      Value::Ifd8s(vals) => {
+        if vals.len() < MAX_LEN {
+          f.debug_tuple("Ifd8s").field(vals).finish()
+        } else {
+          f.debug_tuple("Ifd8s")
+            .field(&format_args!("{} values", vals.len()))
+            .finish()
+        }
+      }
       Value::Rationals(vals) => {
         if vals.len() < MAX_LEN {
           f.debug_tuple("Rationals").field(vals).finish()
@@ -241,4 +323,62 @@ impl fmt::Debug for Value {
       }
     }
   }
-}
\ No newline at end of file
+}
+/// Values shown by `Display` before the rest are elided.
+const MAX_DISPLAYED: usize = 16;
+
+fn write_values<T: fmt::Display>(
+  f: &mut fmt::Formatter,
+  vals: impl ExactSizeIterator<Item = T>,
+) -> fmt::Result {
+  let len = vals.len();
+  for (i, val) in vals.take(MAX_DISPLAYED).enumerate() {
+    if i > 0 {
+      write!(f, " ")?;
+    }
+    write!(f, "{}", val)?;
+  }
+  if len > MAX_DISPLAYED {
+    write!(f, " ... ({} values)", len)?;
+  }
+  Ok(())
+}
+
+/// Space-separated values, elided past the first few, as `gdalinfo` prints tags.
+impl fmt::Display for Value {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Value::Bytes(vals) | Value::Undefined(vals) => write_values(f, vals.iter()),
+      Value::Sbytes(vals) => write_values(f, vals.iter()),
+      Value::Shorts(vals) => write_values(f, vals.iter()),
+      Value::Sshorts(vals) => write_values(f, vals.iter()),
+      Value::Longs(vals) => write_values(f, vals.iter()),
+      Value::Slongs(vals) => write_values(f, vals.iter()),
+      Value::Long8s(vals) | Value::Ifd8s(vals) => write_values(f, vals.iter()),
+      Value::Slong8s(vals) => write_values(f, vals.iter()),
+      Value::Rationals(vals) => write_values(f, vals.iter().map(|(n, d)| format!("{}/{}", n, d))),
+      Value::Srationals(vals) => {
+        write_values(f, vals.iter().map(|(n, d)| format!("{}/{}", n, d)))
+      }
+      Value::Floats(vals) => write_values(f, vals.iter()),
+      Value::Doubles(vals) => write_values(f, vals.iter()),
+      Value::Ascii(string) => write!(f, "{}", string),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_display() {
+    assert_eq!(Value::Shorts(vec![1, 2, 3]).to_string(), "1 2 3");
+    assert_eq!(Value::Rationals(vec![(72, 1)]).to_string(), "72/1");
+    assert_eq!(Value::Ascii("WGS 84|".to_string()).to_string(), "WGS 84|");
+    assert_eq!(
+      Value::Longs((0..20).collect()).to_string(),
+      "0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 ... (20 values)"
+    );
+  }
+}
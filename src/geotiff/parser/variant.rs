@@ -0,0 +1,63 @@
+use super::{endianness::Endianness, TiffParserError};
+
+/// Classic TIFF (magic 42) or BigTIFF (magic 43), which only differ in the
+/// width of counts and offsets in the header and IFDs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) enum TiffVariant {
+  Classic,
+  Big,
+}
+
+impl TiffVariant {
+  pub(super) fn from_u16(magic: u16) -> Result<Self, TiffParserError> {
+    match magic {
+      42 => Ok(TiffVariant::Classic),
+      43 => Ok(TiffVariant::Big),
+      magic => Err(TiffParserError::UnknownMagic(magic)),
+    }
+  }
+
+  /// Size of an offset field, which is also the largest value stored inline in an entry.
+  pub(super) fn offset_size(&self) -> usize {
+    match self {
+      TiffVariant::Classic => 4,
+      TiffVariant::Big => 8,
+    }
+  }
+
+  pub(super) fn entry_count_size(&self) -> usize {
+    match self {
+      TiffVariant::Classic => 2,
+      TiffVariant::Big => 8,
+    }
+  }
+
+  pub(super) fn entry_size(&self) -> usize {
+    match self {
+      TiffVariant::Classic => 12,
+      TiffVariant::Big => 20,
+    }
+  }
+
+  pub(super) fn read_offset(
+    &self,
+    endianness: Endianness,
+    buf: &[u8],
+  ) -> Result<usize, TiffParserError> {
+    match self {
+      TiffVariant::Classic => Ok(endianness.read_u32(buf)? as usize),
+      TiffVariant::Big => Ok(endianness.read_u64(buf)? as usize),
+    }
+  }
+
+  pub(super) fn read_entry_count(
+    &self,
+    endianness: Endianness,
+    buf: &[u8],
+  ) -> Result<usize, TiffParserError> {
+    match self {
+      TiffVariant::Classic => Ok(endianness.read_u16(buf)? as usize),
+      TiffVariant::Big => Ok(endianness.read_u64(buf)? as usize),
+    }
+  }
+}
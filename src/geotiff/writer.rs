@@ -0,0 +1,375 @@
+use std::{fs, path::Path};
+
+use super::{
+  georeference::{
+    GeoTransform, KEY_GEOGRAPHIC_TYPE, KEY_GEOG_ANGULAR_UNITS, KEY_GEOG_CITATION,
+    KEY_MODEL_TYPE, KEY_RASTER_TYPE, TAG_GEO_ASCII_PARAMS, TAG_GEO_KEY_DIRECTORY,
+    TAG_MODEL_PIXEL_SCALE, TAG_MODEL_TIEPOINT, TAG_MODEL_TRANSFORMATION,
+  },
+  geotiff::TAG_GDAL_NODATA,
+  parser::{tags::*, Compression},
+  TiffParserError,
+};
+
+const TAG_PHOTOMETRIC_INTERPRETATION: u16 = 262;
+
+const FIELD_ASCII: u16 = 2;
+const FIELD_SHORT: u16 = 3;
+const FIELD_LONG: u16 = 4;
+const FIELD_DOUBLE: u16 = 12;
+
+const MODEL_TYPE_GEOGRAPHIC: u16 = 2;
+const RASTER_TYPE_PIXEL_IS_AREA: u16 = 1;
+const EPSG_WGS84: u16 = 4326;
+const ANGULAR_UNIT_DEGREE: u16 = 9102;
+const WGS84_CITATION: &str = "WGS 84|";
+
+/// Samples of a single-band raster, row by row from the top left corner.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Samples {
+  Int16(Vec<i16>),
+  Float32(Vec<f32>),
+}
+
+impl Samples {
+  fn len(&self) -> usize {
+    match self {
+      Samples::Int16(vals) => vals.len(),
+      Samples::Float32(vals) => vals.len(),
+    }
+  }
+
+  fn bits_per_sample(&self) -> u16 {
+    match self {
+      Samples::Int16(_) => 16,
+      Samples::Float32(_) => 32,
+    }
+  }
+
+  fn sample_format(&self) -> u16 {
+    match self {
+      Samples::Int16(_) => 2,
+      Samples::Float32(_) => 3,
+    }
+  }
+
+  /// Appends the sample at `index`, or `fill` if it is `None`, in little-endian order.
+  fn push(&self, index: Option<usize>, fill: f64, buf: &mut Vec<u8>) {
+    match self {
+      Samples::Int16(vals) => {
+        let val = index.map_or(fill as i16, |index| vals[index]);
+        buf.extend_from_slice(&val.to_le_bytes());
+      }
+      Samples::Float32(vals) => {
+        let val = index.map_or(fill as f32, |index| vals[index]);
+        buf.extend_from_slice(&val.to_le_bytes());
+      }
+    }
+  }
+}
+
+struct Entry {
+  tag: u16,
+  field: u16,
+  count: usize,
+  bytes: Vec<u8>,
+}
+
+impl Entry {
+  fn shorts(tag: u16, vals: &[u16]) -> Self {
+    let bytes = vals.iter().flat_map(|x| x.to_le_bytes()).collect();
+    Self { tag, field: FIELD_SHORT, count: vals.len(), bytes }
+  }
+
+  fn longs(tag: u16, vals: &[u32]) -> Self {
+    let bytes = vals.iter().flat_map(|x| x.to_le_bytes()).collect();
+    Self { tag, field: FIELD_LONG, count: vals.len(), bytes }
+  }
+
+  fn doubles(tag: u16, vals: &[f64]) -> Self {
+    let bytes = vals.iter().flat_map(|x| x.to_le_bytes()).collect();
+    Self { tag, field: FIELD_DOUBLE, count: vals.len(), bytes }
+  }
+
+  fn ascii(tag: u16, val: &str) -> Self {
+    let mut bytes = val.as_bytes().to_vec();
+    bytes.push(0);
+    Self { tag, field: FIELD_ASCII, count: bytes.len(), bytes }
+  }
+
+  /// SHORT if the value fits, LONG otherwise.
+  fn size(tag: u16, val: usize) -> Result<Self, TiffParserError> {
+    if let Ok(short) = u16::try_from(val) {
+      return Ok(Self::shorts(tag, &[short]));
+    }
+    let long = u32::try_from(val).map_err(|_| TiffParserError::DimensionTooLarge(val))?;
+    Ok(Self::longs(tag, &[long]))
+  }
+}
+
+/// Writes single-band, tiled little-endian GeoTIFFs georeferenced in EPSG:4326.
+#[derive(Debug, Clone)]
+pub struct GeoTiffWriter {
+  width: usize,
+  length: usize,
+  transform: GeoTransform,
+  tile_size: usize,
+  compression: Compression,
+  nodata: Option<f64>,
+}
+
+impl GeoTiffWriter {
+  /// `transform` maps the outer corner of pixels to longitude and latitude.
+  pub fn new(width: usize, length: usize, transform: GeoTransform) -> Self {
+    Self {
+      width,
+      length,
+      transform,
+      tile_size: 256,
+      compression: Compression::None,
+      nodata: None,
+    }
+  }
+
+  pub fn with_compression(mut self, compression: Compression) -> Self {
+    self.compression = compression;
+    self
+  }
+
+  /// Nodata value written to the GDAL_NODATA tag and used to pad edge tiles.
+  pub fn with_nodata(mut self, nodata: f64) -> Self {
+    self.nodata = Some(nodata);
+    self
+  }
+
+  /// Tile width and length, rounded up to a multiple of 16 as TIFF requires.
+  pub fn with_tile_size(mut self, tile_size: usize) -> Self {
+    self.tile_size = tile_size.max(1).next_multiple_of(16);
+    self
+  }
+
+  /// Fails if the raster, padded to whole tiles and left uncompressed with
+  /// samples of `bits_per_sample` bits, is too large for a classic TIFF.
+  pub fn check_size(&self, bits_per_sample: u16) -> Result<(), TiffParserError> {
+    let padded = |size: usize| size.div_ceil(self.tile_size).checked_mul(self.tile_size);
+    let bytes = padded(self.width)
+      .zip(padded(self.length))
+      .and_then(|(width, length)| width.checked_mul(length))
+      .and_then(|pixels| pixels.checked_mul(bits_per_sample as usize / 8))
+      .unwrap_or(usize::MAX);
+    match u32::try_from(bytes) {
+      Ok(_) => Ok(()),
+      Err(_) => Err(TiffParserError::TooLarge(bytes)),
+    }
+  }
+
+  pub fn write<P: AsRef<Path>>(&self, path: P, samples: &Samples) -> Result<(), TiffParserError> {
+    fs::write(path, self.to_bytes(samples)?)?;
+    Ok(())
+  }
+
+  pub fn to_bytes(&self, samples: &Samples) -> Result<Vec<u8>, TiffParserError> {
+    if samples.len() != self.width * self.length {
+      return Err(TiffParserError::SampleCountMismatch(self.width * self.length, samples.len()));
+    }
+
+    let tiles = self.encode_tiles(samples)?;
+    let mut entries = self.entries(samples, &tiles)?;
+    entries.sort_by_key(|entry| entry.tag);
+
+    // header, then the IFD, its out-of-line values and the tiles, each on a word boundary
+    let ifd_size = 2 + entries.len() * 12 + 4;
+    let values_size: usize = entries
+      .iter()
+      .filter(|entry| entry.bytes.len() > 4)
+      .map(|entry| entry.bytes.len().next_multiple_of(2))
+      .sum();
+    let mut offset = 8 + ifd_size + values_size;
+    let mut tile_offsets = Vec::with_capacity(tiles.len());
+    for tile in &tiles {
+      tile_offsets.push(u32::try_from(offset).map_err(|_| TiffParserError::TooLarge(offset))?);
+      offset += tile.len().next_multiple_of(2);
+    }
+    u32::try_from(offset).map_err(|_| TiffParserError::TooLarge(offset))?;
+    if let Some(entry) = entries.iter_mut().find(|entry| entry.tag == TAG_TILE_OFFSETS) {
+      *entry = Entry::longs(TAG_TILE_OFFSETS, &tile_offsets);
+    }
+
+    let mut buf = Vec::with_capacity(offset);
+    buf.extend_from_slice(b"II*\0");
+    buf.extend_from_slice(&8u32.to_le_bytes());
+    buf.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+    let mut values = vec![];
+    let values_start = 8 + ifd_size;
+    for entry in &entries {
+      buf.extend_from_slice(&entry.tag.to_le_bytes());
+      buf.extend_from_slice(&entry.field.to_le_bytes());
+      buf.extend_from_slice(&(entry.count as u32).to_le_bytes());
+      if entry.bytes.len() > 4 {
+        buf.extend_from_slice(&((values_start + values.len()) as u32).to_le_bytes());
+        values.extend_from_slice(&entry.bytes);
+        values.resize(values.len().next_multiple_of(2), 0);
+      } else {
+        let mut inline = entry.bytes.clone();
+        inline.resize(4, 0);
+        buf.extend_from_slice(&inline);
+      }
+    }
+    buf.extend_from_slice(&0u32.to_le_bytes());
+    buf.extend(values);
+    for tile in tiles {
+      buf.extend_from_slice(&tile);
+      buf.resize(buf.len().next_multiple_of(2), 0);
+    }
+
+    Ok(buf)
+  }
+
+  fn encode_tiles(&self, samples: &Samples) -> Result<Vec<Vec<u8>>, TiffParserError> {
+    let tile_size = self.tile_size;
+    let fill = self.nodata.unwrap_or(0.0);
+    let bytes_per_sample = samples.bits_per_sample() as usize / 8;
+    let mut tile = Vec::with_capacity(tile_size * tile_size * bytes_per_sample);
+    let mut tiles = vec![];
+    for tile_row in 0..self.length.div_ceil(tile_size) {
+      for tile_column in 0..self.width.div_ceil(tile_size) {
+        tile.clear();
+        for y in tile_row * tile_size..(tile_row + 1) * tile_size {
+          for x in tile_column * tile_size..(tile_column + 1) * tile_size {
+            let index = (x < self.width && y < self.length).then_some(y * self.width + x);
+            samples.push(index, fill, &mut tile);
+          }
+        }
+        tiles.push(self.compression.compress(&tile)?);
+      }
+    }
+    Ok(tiles)
+  }
+
+  fn entries(&self, samples: &Samples, tiles: &[Vec<u8>]) -> Result<Vec<Entry>, TiffParserError> {
+    let byte_counts: Vec<u32> = tiles.iter().map(|tile| tile.len() as u32).collect();
+    let mut entries = vec![
+      Entry::size(TAG_IMAGE_WIDTH, self.width)?,
+      Entry::size(TAG_IMAGE_LENGTH, self.length)?,
+      Entry::shorts(TAG_BITS_PER_SAMPLE, &[samples.bits_per_sample()]),
+      Entry::shorts(TAG_COMPRESSION, &[self.compression.to_u16()]),
+      // min-is-black grayscale
+      Entry::shorts(TAG_PHOTOMETRIC_INTERPRETATION, &[1]),
+      Entry::shorts(TAG_SAMPLES_PER_PIXEL, &[1]),
+      Entry::shorts(TAG_PLANAR_CONFIGURATION, &[1]),
+      Entry::size(TAG_TILE_WIDTH, self.tile_size)?,
+      Entry::size(TAG_TILE_LENGTH, self.tile_size)?,
+      // offsets are only known once the IFD is laid out
+      Entry::longs(TAG_TILE_OFFSETS, &vec![0; tiles.len()]),
+      Entry::longs(TAG_TILE_BYTE_COUNTS, &byte_counts),
+      Entry::shorts(TAG_SAMPLE_FORMAT, &[samples.sample_format()]),
+      Entry::shorts(TAG_GEO_KEY_DIRECTORY, &[
+        1, 1, 0, 5,
+        KEY_MODEL_TYPE, 0, 1, MODEL_TYPE_GEOGRAPHIC,
+        KEY_RASTER_TYPE, 0, 1, RASTER_TYPE_PIXEL_IS_AREA,
+        KEY_GEOGRAPHIC_TYPE, 0, 1, EPSG_WGS84,
+        KEY_GEOG_CITATION, TAG_GEO_ASCII_PARAMS, WGS84_CITATION.len() as u16, 0,
+        KEY_GEOG_ANGULAR_UNITS, 0, 1, ANGULAR_UNIT_DEGREE,
+      ]),
+      Entry::ascii(TAG_GEO_ASCII_PARAMS, WGS84_CITATION),
+    ];
+
+    let c = &self.transform.0;
+    if c[2] == 0.0 && c[4] == 0.0 {
+      entries.push(Entry::doubles(TAG_MODEL_TIEPOINT, &[0.0, 0.0, 0.0, c[0], c[3], 0.0]));
+      entries.push(Entry::doubles(TAG_MODEL_PIXEL_SCALE, &[c[1], -c[5], 0.0]));
+    } else {
+      entries.push(Entry::doubles(TAG_MODEL_TRANSFORMATION, &[
+        c[1], c[2], 0.0, c[0],
+        c[4], c[5], 0.0, c[3],
+        0.0, 0.0, 0.0, 0.0,
+        0.0, 0.0, 0.0, 1.0,
+      ]));
+    }
+    if let Some(nodata) = self.nodata {
+      entries.push(Entry::ascii(TAG_GDAL_NODATA, &nodata.to_string()));
+    }
+    Ok(entries)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::geotiff::GeoTiff;
+
+  fn transform() -> GeoTransform {
+    GeoTransform([37.0, 0.25, 0.0, 56.0, 0.0, -0.5])
+  }
+
+  #[test]
+  fn test_int16_round_trip() {
+    // two tiles across, the second one mostly padding
+    let pixels: Vec<i16> = (0..60).map(|x| x * 100 - 3000).collect();
+    let writer = GeoTiffWriter::new(20, 3, transform())
+      .with_tile_size(16)
+      .with_compression(Compression::Lzw)
+      .with_nodata(-32768.0);
+    let buf = writer.to_bytes(&Samples::Int16(pixels)).unwrap();
+    let tiff = GeoTiff::from_bytes(&buf).unwrap();
+
+    assert_eq!(tiff.size(), Some((20, 3)));
+    assert_eq!(tiff.nodata(), Some(-32768.0));
+    assert_eq!(tiff.get_pixel(0, 0, 2).unwrap(), Some(-3000.0));
+    assert_eq!(tiff.get_pixel(0, 17, 2).unwrap(), Some(-1300.0));
+    assert_eq!(tiff.get_pixel(0, 19, 0).unwrap(), Some(2900.0));
+
+    let georeference = tiff.georeference().unwrap();
+    assert_eq!(georeference.epsg, Some(EPSG_WGS84));
+    assert_eq!(georeference.transform, transform());
+    assert_eq!(georeference.bounds(20, 3), (37.0, 54.5, 42.0, 56.0));
+  }
+
+  #[test]
+  fn test_float32_round_trip() {
+    let pixels = vec![-0.5, 1.25, 8848.86, -9999.0];
+    let writer = GeoTiffWriter::new(2, 2, transform())
+      .with_compression(Compression::Deflate)
+      .with_nodata(-9999.0);
+    let buf = writer.to_bytes(&Samples::Float32(pixels)).unwrap();
+    let tiff = GeoTiff::from_bytes(&buf).unwrap();
+
+    assert_eq!(tiff.get_pixel(0, 0, 1).unwrap(), Some(-0.5));
+    assert_eq!(tiff.get_pixel(0, 0, 0).unwrap(), Some(8848.86f32 as f64));
+    assert_eq!(tiff.get_pixel(0, 1, 0).unwrap(), None);
+  }
+
+  #[test]
+  fn test_check_size() {
+    let writer = GeoTiffWriter::new(20, 3, transform()).with_tile_size(16);
+    assert!(writer.check_size(16).is_ok());
+
+    // 2^16 pixels square of 32 bits is 16 GiB
+    let writer = GeoTiffWriter::new(65536, 65536, transform());
+    assert!(matches!(writer.check_size(32), Err(TiffParserError::TooLarge(_))));
+    assert!(matches!(
+      GeoTiffWriter::new(usize::MAX, 1, transform()).check_size(16),
+      Err(TiffParserError::TooLarge(usize::MAX))
+    ));
+  }
+
+  #[test]
+  fn test_size_entry() {
+    assert_eq!(Entry::size(TAG_IMAGE_WIDTH, 300).unwrap().field, FIELD_SHORT);
+    assert_eq!(Entry::size(TAG_IMAGE_WIDTH, 70000).unwrap().field, FIELD_LONG);
+    assert!(matches!(
+      Entry::size(TAG_IMAGE_WIDTH, 1 << 32),
+      Err(TiffParserError::DimensionTooLarge(0x1_0000_0000))
+    ));
+  }
+
+  #[test]
+  fn test_sample_count_mismatch() {
+    let writer = GeoTiffWriter::new(2, 2, transform());
+
+    assert!(matches!(
+      writer.to_bytes(&Samples::Int16(vec![0; 3])),
+      Err(TiffParserError::SampleCountMismatch(4, 3))
+    ));
+  }
+}
@@ -0,0 +1,175 @@
+use indicatif::{ProgressBar, ProgressStyle};
+use log::{debug, info, warn};
+use meridian_positioning::GeoRectangle;
+use crate::elevation::elevation::elevations_at_resolution;
+use crate::errors::Error;
+use crate::geotiff::{Compression, GeoTiffWriter, GeoTransform, Samples};
+use crate::utils::write_output;
+
+const NODATA: f32 = -32768.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportFormat
+{
+  Int16,
+  Float32
+}
+
+impl ExportFormat
+{
+  fn bits_per_sample(&self) -> u16
+  {
+    match self
+    {
+      ExportFormat::Int16 => 16,
+      ExportFormat::Float32 => 32
+    }
+  }
+}
+
+/// Writes elevations inside `georectangle` to `{target_path}.tif` as a GeoTIFF in
+/// EPSG:4326 with pixels of `resolution` meters. Points without elevation data are
+/// written as -32768 and marked as nodata. Fails before sampling anything when the
+/// raster is too large for a classic TIFF.
+pub fn export_georectangle(target_path: &str, georectangle: GeoRectangle, resolution: f32,
+                           format: ExportFormat, compression: Compression)
+  -> Result<(), Error>
+{
+  export_with(target_path, georectangle, resolution, format, compression,
+              |coordinates| elevations_at_resolution(coordinates, resolution))
+}
+
+/// Same as [`export_georectangle`], reading each row of elevations from `sample`.
+fn export_with(target_path: &str, georectangle: GeoRectangle, resolution: f32,
+               format: ExportFormat, compression: Compression,
+               mut sample: impl FnMut(&[(f64, f64)]) -> Vec<Result<f32, Error>>)
+  -> Result<(), Error>
+{
+  if resolution.is_nan() || resolution <= 0.0 {
+    return Err(Error::InvalidArgument(format!("resolution must be positive, got {resolution}")));
+  }
+
+  let top_left = georectangle.top_left();
+  let bottom_right = georectangle.bottom_right();
+  let width = (georectangle.width_meters()? / resolution).ceil().max(1.0) as usize;
+  let length = (georectangle.height_meters()? / resolution).ceil().max(1.0) as usize;
+  let transform = GeoTransform([
+    top_left.longitude,
+    (bottom_right.longitude - top_left.longitude) / width as f64,
+    0.0,
+    top_left.latitude,
+    0.0,
+    (bottom_right.latitude - top_left.latitude) / length as f64
+  ]);
+  let writer = GeoTiffWriter::new(width, length, transform)
+    .with_compression(compression)
+    .with_nodata(NODATA as f64);
+  writer.check_size(format.bits_per_sample())?;
+
+  let path = format!("{target_path}.tif");
+  info!("Exporting georectangle {}", &georectangle);
+  info!("Target size:\t\t {}x{} px", width, length);
+  info!("Format:\t\t {:?}, {:?} compression", format, compression);
+  info!("Target path:\t\t {}", path);
+  debug!("Geotransform: {:?}", transform);
+
+  let pb = ProgressBar::new(length as u64);
+  pb.set_style(ProgressStyle::with_template(
+    "{wide_msg} {spinner:.green} [{bar:20.cyan/blue}] \
+    {human_pos:10}/ {human_len:10} ({percent:3}%)",)
+    .unwrap()
+    .progress_chars("█░░"));
+  pb.set_message("Sampling elevations");
+  let mut heights = Vec::with_capacity(width * length);
+  let mut voids = 0;
+  for row in 0..length {
+    pb.set_position(row as u64);
+    let coordinates: Vec<(f64, f64)> = (0..width)
+      .map(|column| transform.pixel_to_model(column as f64 + 0.5, row as f64 + 0.5))
+      .map(|(longitude, latitude)| (latitude, longitude))
+      .collect();
+    for elevation in sample(&coordinates) {
+      heights.push(match elevation {
+        Ok(elevation) => elevation,
+        Err(_) => {
+          voids += 1;
+          NODATA
+        }
+      });
+    }
+  }
+  pb.finish_with_message("Sampling done!");
+  if voids > 0 {
+    warn!("{} of {} pixels have no elevation data", voids, width * length);
+  }
+
+  let samples = match format {
+    ExportFormat::Int16 => Samples::Int16(heights.iter().map(|x| x.round() as i16).collect()),
+    ExportFormat::Float32 => Samples::Float32(heights)
+  };
+
+  write_output(&path, &writer.to_bytes(&samples)?)?;
+  info!("GeoTIFF saved to {}", &path);
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests
+{
+  use super::*;
+  use meridian_positioning::GeoCoordinate;
+  use crate::geotiff::{GeoTiff, TiffParserError};
+
+  fn georectangle(east: f64) -> GeoRectangle
+  {
+    GeoRectangle::new(GeoCoordinate::new(60.01, 30.0, None), GeoCoordinate::new(60.0, east, None))
+  }
+
+  #[test]
+  fn test_export_round_trip()
+  {
+    // the folder is created by the export
+    let folder = std::env::temp_dir().join("meridian_test_export");
+    let _ = std::fs::remove_dir_all(&folder);
+    let target = folder.join("rectangle");
+    let target = target.to_str().unwrap();
+    // elevation is a thousand times the longitude, void east of 30.015
+    let sample = |coordinates: &[(f64, f64)]| coordinates
+      .iter()
+      .map(|&(latitude, longitude)| match longitude < 30.015 {
+        true => Ok((longitude * 1000.0) as f32),
+        false => Err(Error::NoData(latitude, longitude))
+      })
+      .collect();
+    export_with(target, georectangle(30.02), 100.0, ExportFormat::Float32, Compression::Deflate,
+                sample).unwrap();
+    let tiff = GeoTiff::from_file(format!("{target}.tif")).unwrap();
+
+    let (width, length) = tiff.size().unwrap();
+    let rectangle = georectangle(30.02);
+    assert_eq!(width, (rectangle.width_meters().unwrap() / 100.0).ceil() as usize);
+    assert_eq!(length, (rectangle.height_meters().unwrap() / 100.0).ceil() as usize);
+    assert_eq!(tiff.nodata(), Some(NODATA as f64));
+    let (west, south, east, north) = tiff.georeference().unwrap().bounds(width, length);
+    assert!((west - 30.0).abs() < 1e-9 && (east - 30.02).abs() < 1e-9);
+    assert!((south - 60.0).abs() < 1e-9 && (north - 60.01).abs() < 1e-9);
+
+    // rows are read from the bottom, the first column is centred half a pixel east
+    let first = 30.0 + 0.02 / width as f64 / 2.0;
+    assert_eq!(tiff.get_pixel(0, 0, 0).unwrap(), Some((first * 1000.0) as f32 as f64));
+    assert_eq!(tiff.get_pixel(0, width - 1, length - 1).unwrap(), None);
+  }
+
+  #[test]
+  fn test_export_too_large()
+  {
+    let mut sampled = false;
+    let result = export_with("unused", georectangle(31.0), 0.01, ExportFormat::Int16,
+                             Compression::None, |_| {
+                               sampled = true;
+                               vec![]
+                             });
+    assert!(matches!(result, Err(Error::Tiff(TiffParserError::TooLarge(_)))));
+    assert!(!sampled);
+  }
+}
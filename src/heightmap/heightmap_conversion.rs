@@ -7,9 +7,9 @@ use image::{GrayImage, ImageBuffer, Luma};
 use indicatif::{ProgressBar, ProgressStyle};
 use json::object;
 use log::{debug, error, info};
-use meridian_positioning::{CardinalDirection, GeoRectangle};
+use meridian_positioning::{CardinalDirection, GeoCoordinate, GeoRectangle};
 use num_derive::FromPrimitive;
-use crate::elevation::elevation::Elevation;
+use crate::elevation::elevation::elevations_at_resolution;
 use crate::errors::Error;
 use crate::utils::replace_extension;
 
@@ -96,18 +96,19 @@ pub fn convert_georectangle(target_path: &str, georectangle: GeoRectangle,
     .progress_chars("█░░"));
   pb1.set_message(format!("Finding min/max"));
   let mut min_max = (i16::MAX, i16::MIN);
+  let resolution = (square.width_meters()? / size as f32)
+    .min(square.height_meters()? / size as f32);
+  debug!("Ground resolution: {} m/px", resolution);
   let mut table: Vec<Vec<i16>> = vec![vec![0; size]; size];
   for i in 0..size {
     pb1.set_position(i as u64);
     let base_coordinate = square.top_left()
       .at_distance_and_azimuth(i as f32 * square.height_meters()? / size as f32,
                                CardinalDirection::South.to_degrees())?;
-    for j in 0..size {
-      let elevation = base_coordinate
-        .at_distance_and_azimuth(j as f32 * square.width_meters()? / size as f32,
-                                 CardinalDirection::East.to_degrees())?
-        .elevation()
-        .unwrap_or(0.0);
+    let elevations = sample_row(&base_coordinate, size, square.width_meters()? / size as f32,
+                                resolution)?;
+    for (j, elevation) in elevations.into_iter().enumerate() {
+      let elevation = elevation.unwrap_or(0.0);
       min_max.0 = elevation.min(min_max.0 as f32) as i16;
       min_max.1 = elevation.max(min_max.1 as f32) as i16;
       table[i][j] = elevation as i16;
@@ -166,6 +167,23 @@ pub fn convert_georectangle(target_path: &str, georectangle: GeoRectangle,
   save_image(image.as_ref(), &path)
 }
 
+/// Elevations of `count` points `step` meters apart eastwards of `start`, read
+/// from pixels no larger than `resolution` meters. Voids are `None`.
+pub(crate) fn sample_row(start: &GeoCoordinate, count: usize, step: f32, resolution: f32)
+  -> Result<Vec<Option<f32>>, Error>
+{
+  let mut coordinates = Vec::with_capacity(count);
+  for j in 0..count {
+    let coordinate = start
+      .at_distance_and_azimuth(j as f32 * step, CardinalDirection::East.to_degrees())?;
+    coordinates.push((coordinate.latitude, coordinate.longitude));
+  }
+  Ok(elevations_at_resolution(&coordinates, resolution)
+    .into_iter()
+    .map(|x| x.ok())
+    .collect())
+}
+
 fn save_image(image: &ImageBuffer<Luma<u8>, Vec<u8>>, path: &str)
   -> Result<(), Error>
 {
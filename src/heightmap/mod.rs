@@ -2,6 +2,11 @@ mod heightmap_conversion;
 pub use heightmap_conversion::convert_georectangle;
 pub use heightmap_conversion::ImageFormat;
 pub use heightmap_conversion::Resolution;
+pub(crate) use heightmap_conversion::sample_row;
+
+mod geotiff_export;
+pub use geotiff_export::export_georectangle;
+pub use geotiff_export::ExportFormat;
 
 mod prefetcher;
 pub use prefetcher::ElevationPrefetcher;
\ No newline at end of file
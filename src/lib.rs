@@ -1,7 +1,7 @@
 use log::warn;
-pub use crate::elevation::elevation::elevation_at;
+pub use crate::elevation::elevation::{elevation_at, elevations_at};
 
-mod geotiff;
+pub mod geotiff;
 mod tile_storage;
 pub mod errors;
 pub mod config;
@@ -12,6 +12,7 @@ pub mod heightmap;
 mod tile_map;
 mod coordinate_system;
 pub use coordinate_system::Chunk;
+pub use tile_storage::TileIdentity;
 
 pub fn init_logger() -> bool
 {
@@ -76,7 +76,10 @@ impl NetworkFetcher
     std::fs::create_dir_all(target[..target.rfind(MAIN_SEPARATOR).unwrap()]
       .to_string())
       .unwrap();
-    let mut file = std::fs::File::create(&target)?;
+    // written aside and renamed over the tile once complete, so that a tile
+    // being read is never truncated and a failed download leaves no partial tile
+    let partial = format!("{target}.part");
+    let mut file = std::fs::File::create(&partial)?;
     debug!("File status: OK");
 
     let mut downloaded: u64 = 0;
@@ -89,6 +92,8 @@ impl NetworkFetcher
       downloaded = new;
       pb.set_position(new);
     }
+    drop(file);
+    std::fs::rename(&partial, &target)?;
     pb.finish_with_message(format!("Downloaded {} to {}", signature, target));
     println!();
 
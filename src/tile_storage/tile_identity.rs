@@ -1,33 +1,60 @@
+use std::fs;
 use log::{debug};
 use crate::errors::Error;
 use chrono::Utc;
+use once_cell::sync::OnceCell;
 use crate::geotiff::GeoTiff;
+use crate::utils::decode_threads;
 
 pub struct TileIdentity
 {
   pub file_path: String,
   pub data: Box<GeoTiff>,
-  pub size: (usize, usize)
+  pub size: (usize, usize),
+  range: OnceCell<Option<(f64, f64)>>
 }
 
 impl TileIdentity
 {
   pub fn new(file_path: String) -> Result<Self, Error>
   {
-    debug!("Decoding tiff file from {}", file_path);
+    debug!("Opening tiff file from {}", file_path);
     let start = Utc::now().time();
-    let data_raw = GeoTiff::from_file(&file_path)?;
+    // cached tiles may be rewritten by a download while they are open, so they
+    // are read into memory rather than mapped
+    let data_raw = GeoTiff::from_bytes(&fs::read(&file_path)?)?;
     let end = Utc::now().time();
-    debug!("Decoding status: OK");
-    debug!("Decoding tiff file from {} took {}ms", file_path, (end - start).num_milliseconds());
+    debug!("Opening status: OK");
+    debug!("Opening tiff file from {} took {}ms", file_path, (end - start).num_milliseconds());
+    Self::from_geotiff(file_path, data_raw)
+  }
 
-    let im_size = imagesize::size(&file_path)?;
+  /// Wraps a file that is already open, `file_path` only naming it in errors.
+  pub fn from_geotiff(file_path: String, data_raw: GeoTiff) -> Result<Self, Error>
+  {
+    let im_size = data_raw
+      .size()
+      .ok_or(Error::NoRaster(file_path.clone()))?;
     debug!("Image size: {:?}", im_size);
+    debug!("Resolution levels: {}", data_raw.levels());
+    debug!("NoData: {:?}", data_raw.nodata());
+    if let Some(georeference) = data_raw.georeference() {
+      debug!("Georeference: {:?}", georeference);
+      debug!("Bounds: {:?}", georeference.bounds(im_size.0, im_size.1));
+    }
 
     Ok(Self {
       file_path,
       data: Box::new(data_raw),
-      size: (im_size.width, im_size.height)
+      size: im_size,
+      range: OnceCell::new()
     })
   }
+
+  /// Lowest and highest elevation of the full resolution raster, `None` when it
+  /// holds only voids. Decodes the raster on every core on the first call.
+  pub fn elevation_range(&self) -> Result<Option<(f64, f64)>, Error>
+  {
+    Ok(*self.range.get_or_try_init(|| self.data.level_range(0, decode_threads()))?)
+  }
 }
\ No newline at end of file
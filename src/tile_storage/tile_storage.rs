@@ -40,7 +40,6 @@ impl TileStorage
     }
   }
 
-  #[allow(dead_code)]
   pub fn has(&self, signature: TileSignature) -> bool
   {
     return self.table.contains_key(&signature);
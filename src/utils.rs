@@ -1,4 +1,10 @@
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::Write;
+use std::path::Path;
 use std::sync::Mutex;
+use std::thread;
 use meridian_positioning::errors::PositioningError;
 use meridian_positioning::GeoCoordinate;
 use once_cell::sync::Lazy;
@@ -29,4 +35,21 @@ pub fn replace_extension(path: &str, new_extension: &str) -> String
   let i = p.rfind('.').unwrap();
   p.replace_range((i + 1).., new_extension);
   return p;
+}
+
+/// Number of threads rasters are decoded on, one per available core.
+pub(crate) fn decode_threads() -> usize
+{
+  static THREADS: Lazy<usize> = Lazy::new(||
+    thread::available_parallelism().map_or(1, |threads| threads.get()));
+  *THREADS
+}
+
+/// Writes `bytes` to `path`, creating the missing folders leading to it.
+pub(crate) fn write_output(path: &str, bytes: &[u8]) -> io::Result<()>
+{
+  if let Some(parent) = Path::new(path).parent() {
+    fs::create_dir_all(parent)?;
+  }
+  File::create(path)?.write_all(bytes)
 }
\ No newline at end of file